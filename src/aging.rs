@@ -0,0 +1,110 @@
+use crate::{HeapError, Task};
+use std::str::FromStr;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+const DEFAULT_LINEAR_RATE: f64 = 0.05;
+const DEFAULT_EXPONENTIAL_RATE: f64 = 0.02;
+const DEFAULT_CAP: f64 = 3.0;
+
+// How a task's weight grows while it waits on the heap, so old low-weight tasks still
+// come up eventually. Rates are per day, as a fraction of the base weight. Named in the
+// config file as `none`, `linear[:rate]`, `exponential[:rate]` or `capped[:rate[:cap]]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aging {
+    None,
+    // The base weight, plus `rate` times the base weight per day.
+    Linear(f64),
+    // Compounds by `rate` per day.
+    Exponential(f64),
+    // Linear, but never more than `cap` times the base weight.
+    Capped(f64, f64),
+}
+impl Aging {
+    // The weight a pop uses for `task` at unix time `now`.
+    pub fn effective_weight(&self, task: &Task, now: i64) -> u32 {
+        let days = (now - task.get_created()).max(0) as f64 / SECONDS_PER_DAY;
+        let factor = match *self {
+            Aging::None => 1.0,
+            Aging::Linear(rate) => 1.0 + rate * days,
+            Aging::Exponential(rate) => (1.0 + rate).powf(days),
+            Aging::Capped(rate, cap) => (1.0 + rate * days).min(cap),
+        };
+        (task.get_weight() as f64 * factor)
+            .round()
+            .min(u32::MAX as f64) as u32
+    }
+}
+impl Default for Aging {
+    fn default() -> Self {
+        Aging::Linear(DEFAULT_LINEAR_RATE)
+    }
+}
+impl FromStr for Aging {
+    type Err = HeapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let numbers = parts
+            .map(|part| match part.parse::<f64>() {
+                Ok(number) if number >= 0.0 && number.is_finite() => Ok(number),
+                _ => Err(HeapError::InvalidConfig(format!(
+                    "aging parameter \"{part}\" is not a positive number"
+                ))),
+            })
+            .collect::<Result<Vec<f64>, HeapError>>()?;
+        match (name, numbers.as_slice()) {
+            ("none", []) => Ok(Aging::None),
+            ("linear", []) => Ok(Aging::Linear(DEFAULT_LINEAR_RATE)),
+            ("linear", [rate]) => Ok(Aging::Linear(*rate)),
+            ("exponential", []) => Ok(Aging::Exponential(DEFAULT_EXPONENTIAL_RATE)),
+            ("exponential", [rate]) => Ok(Aging::Exponential(*rate)),
+            ("capped", []) => Ok(Aging::Capped(DEFAULT_LINEAR_RATE, DEFAULT_CAP)),
+            ("capped", [rate]) => Ok(Aging::Capped(*rate, DEFAULT_CAP)),
+            ("capped", [rate, cap]) => Ok(Aging::Capped(*rate, *cap)),
+            _ => Err(HeapError::InvalidConfig(format!(
+                "unknown aging curve \"{s}\""
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_grow_with_age() {
+        let mut task = Task::from_arg("Old chore");
+        task.set_weight("10").unwrap();
+        let now = task.get_created();
+        let in_days = |days: i64| now + days * SECONDS_PER_DAY as i64;
+
+        assert_eq!(Aging::Linear(0.5).effective_weight(&task, now), 10);
+        assert_eq!(Aging::Linear(0.5).effective_weight(&task, in_days(4)), 30);
+        assert_eq!(
+            Aging::Exponential(1.0).effective_weight(&task, in_days(3)),
+            80
+        );
+        assert_eq!(
+            Aging::Capped(0.5, 2.0).effective_weight(&task, in_days(10)),
+            20
+        );
+        assert_eq!(Aging::None.effective_weight(&task, in_days(100)), 10);
+        // A clock behind the creation time does not shrink the weight.
+        assert_eq!(Aging::Linear(0.5).effective_weight(&task, in_days(-4)), 10);
+    }
+
+    #[test]
+    fn curves_parse_from_config_values() {
+        assert_eq!("none".parse::<Aging>().unwrap(), Aging::None);
+        assert_eq!("linear:0.1".parse::<Aging>().unwrap(), Aging::Linear(0.1));
+        assert_eq!(
+            "capped:0.1:4".parse::<Aging>().unwrap(),
+            Aging::Capped(0.1, 4.0)
+        );
+        assert!("exponential:-1".parse::<Aging>().is_err());
+        assert!("none:2".parse::<Aging>().is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    HeapError, Tasks,
+    storage::{parse_csv_heap, write_csv_heap},
+};
+use chrono::Local;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+pub const DEFAULT_RETENTION: usize = 10;
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3f";
+
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub timestamp: String,
+    pub reason: String,
+}
+
+// Timestamped CSV snapshots of one heap, taken before destructive operations and
+// kept in `backups/<heap>/` inside the data directory. Only the newest `retention`
+// snapshots are kept; a retention of 0 turns backups off.
+pub struct Backups {
+    dir: Option<PathBuf>,
+    retention: usize,
+}
+impl Backups {
+    pub fn new(dir: impl Into<PathBuf>, retention: usize) -> Self {
+        Self {
+            dir: Some(dir.into()),
+            retention,
+        }
+    }
+    pub fn disabled() -> Self {
+        Self {
+            dir: None,
+            retention: 0,
+        }
+    }
+    pub fn snapshot(&self, heap: &Tasks, reason: &str) -> Result<Option<PathBuf>, HeapError> {
+        let Some(path) = self.next_path(reason)? else {
+            return Ok(None);
+        };
+        write_csv_heap(&path, heap)?;
+        self.prune()?;
+        Ok(Some(path))
+    }
+    // Copies a CSV database as it is on disk, e.g. before it is migrated.
+    pub fn snapshot_file(&self, source: &Path, reason: &str) -> Result<Option<PathBuf>, HeapError> {
+        let Some(path) = self.next_path(reason)? else {
+            return Ok(None);
+        };
+        fs::copy(source, &path)?;
+        self.prune()?;
+        Ok(Some(path))
+    }
+    // Newest first.
+    pub fn list(&self) -> Result<Vec<Snapshot>, HeapError> {
+        let Some(dir) = &self.dir else {
+            return Ok(Vec::new());
+        };
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let Some((timestamp, reason)) = stem.split_once('-') else {
+                continue;
+            };
+            let Ok(time) = chrono::NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+            else {
+                continue;
+            };
+            snapshots.push(Snapshot {
+                timestamp: time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                reason: reason.to_owned(),
+                path,
+            });
+        }
+        // The file names sort chronologically.
+        snapshots.sort_by(|a, b| b.path.cmp(&a.path));
+        Ok(snapshots)
+    }
+    pub fn load(&self, snapshot: &Snapshot) -> Result<Tasks, HeapError> {
+        let (heap, _) = parse_csv_heap(&fs::read_to_string(&snapshot.path)?)?;
+        Ok(heap)
+    }
+    fn next_path(&self, reason: &str) -> Result<Option<PathBuf>, HeapError> {
+        let Some(dir) = &self.dir else {
+            return Ok(None);
+        };
+        if self.retention == 0 {
+            return Ok(None);
+        }
+        fs::create_dir_all(dir)?;
+        // Snapshots taken within the same millisecond would share a name.
+        loop {
+            let timestamp = Local::now().format(TIMESTAMP_FORMAT);
+            let path = dir.join(format!("{timestamp}-{reason}.csv"));
+            if !path.exists() {
+                return Ok(Some(path));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    fn prune(&self) -> Result<(), HeapError> {
+        for snapshot in self.list()?.into_iter().skip(self.retention) {
+            fs::remove_file(snapshot.path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Task;
+
+    #[test]
+    fn only_the_newest_snapshots_are_kept() {
+        let dir = std::env::temp_dir().join(format!("task-heap-backups-{}", std::process::id()));
+        let backups = Backups::new(&dir, 2);
+        let mut heap = Tasks::new();
+        for name in ["a", "b", "c"] {
+            let task = Task::from_arg(name);
+            heap.insert(task.get_hash(), task);
+            backups.snapshot(&heap, "reset").unwrap();
+        }
+        let snapshots = backups.list().unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].reason, "reset");
+        assert_eq!(backups.load(&snapshots[0]).unwrap().len(), 3);
+        assert_eq!(backups.load(&snapshots[1]).unwrap().len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use crate::HeapError;
+use std::str::FromStr;
+
+const SECONDS_PER_HOUR: f64 = 3_600.0;
+const DEFAULT_COOLDOWN_HOURS: f64 = 24.0;
+const DEFAULT_DECAY: f64 = 0.5;
+const DEFAULT_BOOST: f64 = 1.5;
+
+// What answering "n" at the chicken-or-penguin prompt does to the task, besides being
+// counted. Named in the config file as `none`, `cooldown[:hours]`, `decay[:factor]` or
+// `boost[:factor]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChickenPolicy {
+    // Only count the rejection.
+    #[default]
+    None,
+    // Keep the task out of pops for this many hours.
+    Cooldown(f64),
+    // Multiply the weight by the factor (below 1) once per rejection.
+    Decay(f64),
+    // Multiply the weight by the factor (above 1) once per rejection: you can't run
+    // forever.
+    Boost(f64),
+}
+impl ChickenPolicy {
+    pub fn weight_factor(&self, rejections: u32) -> f64 {
+        match *self {
+            ChickenPolicy::Decay(factor) | ChickenPolicy::Boost(factor) => {
+                factor.powi(rejections.min(i32::MAX as u32) as i32)
+            }
+            ChickenPolicy::None | ChickenPolicy::Cooldown(_) => 1.0,
+        }
+    }
+    // Unix time until which a task rejected at `now` sits out, if it does.
+    pub fn cooldown_until(&self, now: i64) -> Option<i64> {
+        match *self {
+            ChickenPolicy::Cooldown(hours) => Some(now + (hours * SECONDS_PER_HOUR) as i64),
+            _ => None,
+        }
+    }
+}
+impl FromStr for ChickenPolicy {
+    type Err = HeapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (s.as_str(), None),
+        };
+        let number = |default: f64| match parameter {
+            None => Ok(default),
+            Some(parameter) => match parameter.parse::<f64>() {
+                Ok(number) if number >= 0.0 && number.is_finite() => Ok(number),
+                _ => Err(HeapError::InvalidConfig(format!(
+                    "chicken policy parameter \"{parameter}\" is not a positive number"
+                ))),
+            },
+        };
+        match name {
+            "none" if parameter.is_none() => Ok(ChickenPolicy::None),
+            "cooldown" => Ok(ChickenPolicy::Cooldown(number(DEFAULT_COOLDOWN_HOURS)?)),
+            "decay" => Ok(ChickenPolicy::Decay(number(DEFAULT_DECAY)?)),
+            "boost" => Ok(ChickenPolicy::Boost(number(DEFAULT_BOOST)?)),
+            _ => Err(HeapError::InvalidConfig(format!(
+                "unknown chicken policy \"{s}\""
+            ))),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use task_heap::recurrence::Recurrence;
+
 pub enum Commands {
     Push(String),
     Name(String),
@@ -5,28 +7,52 @@ pub enum Commands {
     Weight(String),
     Tag(Vec<String>),
     Untag(Vec<String>),
+    Repeat(Option<Recurrence>),
+    After(String),
+    NotAfter(String),
+    Parent(String),
+    NoParent,
     ClearTags(String),
-    Pop,
+    Pop(usize),
+    MetaPop(usize),
+    Peek,
+    Done,
+    Stats(bool),
+    Since(i64),
+    Until(i64),
     Delete(String),
     Edit(String),
     List,
+    Snoozed,
+    Tree,
+    Snooze(String, i64),
     Reset,
+    Undo(usize),
+    Redo(usize),
+    History(usize),
+    Restore(Option<usize>),
     Help,
+    ListHeaps,
+    NewHeap(String),
+    EditHeap(String),
+    DeleteHeap(String),
 }
 impl Commands {
-    pub fn needs_non_empty_heap(&self) -> bool {
-        matches!(
-            self,
-            Self::ClearTags(_) | Self::Pop | Self::Delete(_) | Self::Edit(_) | Self::List
-        )
-    }
     pub fn is_valid_for(&self, command: &Commands) -> bool {
         match (command, self) {
-            // Push accepts everything except Untag
-            (Commands::Push(_), Self::Description(_) | Self::Weight(_) | Self::Tag(_)) => true,
+            // Push accepts everything except Untag, NotAfter and NoParent
+            (
+                Commands::Push(_),
+                Self::Description(_)
+                | Self::Weight(_)
+                | Self::Tag(_)
+                | Self::Repeat(_)
+                | Self::After(_)
+                | Self::Parent(_),
+            ) => true,
 
-            // Pop/Delete ONLY accept filtering tags
-            (Commands::Pop, Self::Tag(_)) => true,
+            // Pop/Peek/Delete ONLY accept filtering tags
+            (Commands::Pop(_) | Commands::MetaPop(_) | Commands::Peek, Self::Tag(_)) => true,
             (Commands::Delete(_), Self::Tag(_)) => true,
 
             // Edit accepts specific fields
@@ -36,11 +62,23 @@ impl Commands {
                 | Self::Description(_)
                 | Self::Weight(_)
                 | Self::Tag(_)
-                | Self::Untag(_),
+                | Self::Untag(_)
+                | Self::Repeat(_)
+                | Self::After(_)
+                | Self::NotAfter(_)
+                | Self::Parent(_)
+                | Self::NoParent,
             ) => true,
 
+            // Heaps have a weight, and can be renamed
+            (Commands::NewHeap(_), Self::Weight(_)) => true,
+            (Commands::EditHeap(_), Self::Name(_) | Self::Weight(_)) => true,
+
+            // Completed tasks are filtered by tag and by completion date
+            (Commands::Done, Self::Tag(_) | Self::Since(_) | Self::Until(_)) => true,
+
             //List accepts tag and weight (for now equal, but <> in future)
-            (Commands::List, Self::Tag(_) | Self::Weight(_)) => true,
+            (Commands::List, Self::Tag(_) | Self::Weight(_) | Self::Snoozed | Self::Tree) => true,
 
             // Default to false for everything else
             _ => false,
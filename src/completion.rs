@@ -0,0 +1,135 @@
+use crate::{HeapError, Task, csv, storage::write_atomically};
+use std::{fs, io::Write, path::PathBuf};
+
+// A popped task as it was when it was done, so its tags, weight and rejections are
+// still known.
+#[derive(Clone)]
+pub struct Completion {
+    pub completed: i64,
+    pub task: Task,
+}
+impl Completion {
+    fn to_record(&self) -> String {
+        csv::encode_record([self.completed.to_string(), self.task.to_string()])
+    }
+    fn from_record(record: &str) -> Result<Self, HeapError> {
+        let corrupt = || HeapError::CorruptData(record.to_owned());
+        let fields = csv::decode_record(record)?;
+        let [completed, task] = fields.as_slice() else {
+            return Err(corrupt());
+        };
+        Ok(Self {
+            completed: completed.parse().map_err(|_| corrupt())?,
+            task: task.parse()?,
+        })
+    }
+}
+
+// Every task popped from one heap, oldest first, kept next to the heap's database.
+// Undoing a pop takes its completion back out, so the file is rewritten on `save`.
+pub struct CompletionLog {
+    path: Option<PathBuf>,
+    completions: Vec<Completion>,
+    changed: bool,
+}
+impl CompletionLog {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, HeapError> {
+        let path = path.into();
+        let mut log = Self::in_memory();
+        if path.exists() {
+            let data = fs::read_to_string(&path)?;
+            for record in csv::split_records(&data) {
+                log.completions.push(Completion::from_record(record)?);
+            }
+        }
+        log.path = Some(path);
+        Ok(log)
+    }
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            completions: Vec::new(),
+            changed: false,
+        }
+    }
+    pub fn record(&mut self, task: Task, completed: i64) {
+        self.completions.push(Completion { completed, task });
+        self.changed = true;
+    }
+    // Takes back the latest completion of the task named `name`.
+    pub fn retract(&mut self, name: &str) -> Option<Completion> {
+        let index = self
+            .completions
+            .iter()
+            .rposition(|completion| completion.task.get_name() == name)?;
+        self.changed = true;
+        Some(self.completions.remove(index))
+    }
+    pub fn all(&self) -> &[Completion] {
+        &self.completions
+    }
+    // Completions of tasks holding all of `tags`, done within `since..=until`
+    // (timestamps, either end open when None), oldest first.
+    pub fn query(
+        &self,
+        tags: &[String],
+        since: Option<i64>,
+        until: Option<i64>,
+    ) -> Vec<&Completion> {
+        self.completions
+            .iter()
+            .filter(|completion| completion.task.has_tags(tags))
+            .filter(|completion| since.is_none_or(|since| completion.completed >= since))
+            .filter(|completion| until.is_none_or(|until| completion.completed <= until))
+            .collect()
+    }
+    pub fn save(&mut self) -> Result<(), HeapError> {
+        let Some(path) = &self.path else {
+            self.changed = false;
+            return Ok(());
+        };
+        if !self.changed {
+            return Ok(());
+        }
+        write_atomically(path, |writer| {
+            for completion in &self.completions {
+                writeln!(writer, "{}", completion.to_record())?;
+            }
+            Ok(())
+        })?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completions_survive_reload_and_filter_by_tag_and_date() {
+        let dir = std::env::temp_dir().join(format!("task-heap-done-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("default.done");
+        let _ = fs::remove_file(&path);
+
+        let mut log = CompletionLog::open(&path).unwrap();
+        let mut task = Task::from_arg("Write tests, again");
+        task.add_tags(vec!["code".to_owned()]).set_rejections(2);
+        log.record(task, 100);
+        log.record(Task::from_arg("Water plants"), 200);
+        log.record(Task::from_arg("Water plants"), 300);
+        assert_eq!(log.retract("Water plants").unwrap().completed, 300);
+        log.save().unwrap();
+
+        let log = CompletionLog::open(&path).unwrap();
+        assert_eq!(log.all().len(), 2);
+        let code = log.query(&["code".to_owned()], None, None);
+        assert_eq!(code.len(), 1);
+        assert_eq!(code[0].task.get_name(), "Write tests, again");
+        assert_eq!(code[0].task.get_rejections(), 2);
+        let later = log.query(&[], Some(150), Some(250));
+        assert_eq!(later[0].task.get_name(), "Water plants");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
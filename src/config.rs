@@ -0,0 +1,111 @@
+use crate::{
+    HeapError,
+    aging::Aging,
+    backup,
+    chicken::ChickenPolicy,
+    selection::Selection,
+    storage::{Backend, DEFAULT_HEAP, get_data_dir, registry::validate_name},
+};
+use std::{env, fs, time::Duration};
+
+// Environment variables that override a key from the config file.
+const ENV_OVERRIDES: [(&str, &str); 7] = [
+    ("backend", "TASK_HEAP_BACKEND"),
+    ("lock_timeout", "TASK_HEAP_LOCK_TIMEOUT"),
+    ("default_heap", "TASK_HEAP_DEFAULT_HEAP"),
+    ("backup_retention", "TASK_HEAP_BACKUP_RETENTION"),
+    ("selection", "TASK_HEAP_SELECTION"),
+    ("aging", "TASK_HEAP_AGING"),
+    ("chicken_policy", "TASK_HEAP_CHICKEN_POLICY"),
+];
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Settings are read from a `config` file of `key = value` lines in the data directory.
+pub struct Config {
+    pub backend: Backend,
+    // How long to wait for another process to release the heap.
+    pub lock_timeout: Duration,
+    // Heap used when --heap is not given.
+    pub default_heap: String,
+    // Number of snapshots kept per heap in backups/.
+    pub backup_retention: usize,
+    // How a pop chooses among the candidates, unless --strategy says otherwise.
+    pub selection: Selection,
+    // How weights grow while tasks wait.
+    pub aging: Aging,
+    // What turning down a popped task does to it.
+    pub chicken_policy: ChickenPolicy,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Csv,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            default_heap: DEFAULT_HEAP.to_owned(),
+            backup_retention: backup::DEFAULT_RETENTION,
+            selection: Selection::default(),
+            aging: Aging::default(),
+            chicken_policy: ChickenPolicy::default(),
+        }
+    }
+}
+impl Config {
+    pub fn load() -> Result<Self, HeapError> {
+        let mut config = Config::default();
+        let path = get_data_dir().join("config");
+        if path.exists() {
+            for (number, line) in fs::read_to_string(&path)?.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let Some((key, value)) = line.split_once('=') else {
+                    return Err(HeapError::InvalidConfig(format!(
+                        "line {} is not a key = value pair",
+                        number + 1
+                    )));
+                };
+                config.set(key.trim(), value.trim())?;
+            }
+        }
+        for (key, var) in ENV_OVERRIDES {
+            if let Ok(value) = env::var(var) {
+                config.set(key, &value)?;
+            }
+        }
+        Ok(config)
+    }
+    fn set(&mut self, key: &str, value: &str) -> Result<(), HeapError> {
+        match key {
+            "backend" => self.backend = value.parse()?,
+            "selection" => self.selection = value.parse()?,
+            "aging" => self.aging = value.parse()?,
+            "chicken_policy" => self.chicken_policy = value.parse()?,
+            "lock_timeout" => {
+                let seconds = value.parse().map_err(|_| {
+                    HeapError::InvalidConfig(format!(
+                        "lock_timeout \"{value}\" is not a number of seconds"
+                    ))
+                })?;
+                self.lock_timeout = Duration::from_secs(seconds);
+            }
+            "backup_retention" => {
+                self.backup_retention = value.parse().map_err(|_| {
+                    HeapError::InvalidConfig(format!(
+                        "backup_retention \"{value}\" is not a number of snapshots"
+                    ))
+                })?;
+            }
+            "default_heap" => {
+                validate_name(value)?;
+                self.default_heap = value.to_owned();
+            }
+            unknown => {
+                return Err(HeapError::InvalidConfig(format!(
+                    "unknown key \"{unknown}\""
+                )));
+            }
+        }
+        Ok(())
+    }
+}
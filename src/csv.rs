@@ -0,0 +1,177 @@
+use crate::HeapError;
+use std::borrow::Cow;
+
+const DELIMITER: char = ',';
+const QUOTE: char = '"';
+
+// RFC 4180 quoting. Fields are quoted only when needed, so plain rows look exactly
+// like the unquoted rows older versions wrote.
+pub fn encode_field(field: &str) -> Cow<'_, str> {
+    let needs_quotes = field.contains([DELIMITER, QUOTE, '\n', '\r'])
+        || field.starts_with(char::is_whitespace)
+        || field.ends_with(char::is_whitespace);
+    if needs_quotes {
+        Cow::Owned(format!("\"{}\"", field.replace(QUOTE, "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+pub fn encode_record<I, S>(fields: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|field| encode_field(field.as_ref()).into_owned())
+        .collect();
+    // A lone empty field would otherwise be written as a blank line, which readers skip.
+    if fields.len() == 1 && fields[0].is_empty() {
+        return "\"\"".to_owned();
+    }
+    fields.join(",")
+}
+// Unquoted fields are trimmed, as the legacy format always was. Quoted fields are kept
+// verbatim, which is how leading/trailing whitespace survives a round-trip.
+pub fn decode_record(record: &str) -> Result<Vec<String>, HeapError> {
+    let mut fields = Vec::new();
+    let mut chars = record.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&QUOTE) {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some(QUOTE) if chars.peek() == Some(&QUOTE) => {
+                        chars.next();
+                        field.push(QUOTE);
+                    }
+                    Some(QUOTE) => break,
+                    Some(c) => field.push(c),
+                    None => return Err(HeapError::CorruptData(record.to_owned())),
+                }
+            }
+            // Be lenient with text after the closing quote, like most CSV readers.
+            while let Some(c) = chars.next_if(|c| *c != DELIMITER) {
+                field.push(c);
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != DELIMITER) {
+                field.push(c);
+            }
+            field = field.trim().to_owned();
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            break;
+        }
+    }
+    Ok(fields)
+}
+// Splits a document into records. Line breaks inside quoted fields belong to the
+// record; blank lines are skipped. Like `decode_record`, only a quote opening a field
+// starts a quoted one, so legacy rows with stray quotes stay on their own line.
+pub fn split_records(data: &str) -> Vec<&str> {
+    let mut records = Vec::new();
+    let mut in_quotes = false;
+    let mut field_start = true;
+    let mut start = 0;
+    let mut chars = data.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if in_quotes {
+            // A doubled quote is an escaped one and keeps the field open.
+            if c == QUOTE && chars.next_if(|(_, c)| *c == QUOTE).is_none() {
+                in_quotes = false;
+            }
+            continue;
+        }
+        match c {
+            QUOTE if field_start => in_quotes = true,
+            '\n' => {
+                records.push(&data[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+        field_start = c == DELIMITER || c == '\n';
+    }
+    records.push(&data[start..]);
+    records
+        .into_iter()
+        .map(|record| record.strip_suffix('\r').unwrap_or(record))
+        .filter(|record| !record.trim().is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn field_strategy() -> impl Strategy<Value = String> {
+        proptest::string::string_regex("[a-z ,\"\r\n\t]{0,12}").unwrap()
+    }
+
+    #[test]
+    fn legacy_rows_decode_unchanged() {
+        let fields = decode_record("Clean the garage, sweep first ,100,home chores").unwrap();
+        assert_eq!(
+            fields,
+            ["Clean the garage", "sweep first", "100", "home chores"]
+        );
+    }
+
+    #[test]
+    fn special_characters_are_quoted() {
+        assert_eq!(encode_field("Buy milk, eggs"), "\"Buy milk, eggs\"");
+        assert_eq!(encode_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(encode_field(" padded"), "\" padded\"");
+        assert_eq!(encode_field("plain"), "plain");
+    }
+
+    #[test]
+    fn stray_quotes_in_legacy_rows_do_not_join_lines() {
+        let document = "Say \"hi,desc,100\nWrite tests,,5,home\n\"Buy milk, eggs\",\"a\nb\",1\n";
+        let records = split_records(document);
+        assert_eq!(
+            records,
+            [
+                "Say \"hi,desc,100",
+                "Write tests,,5,home",
+                "\"Buy milk, eggs\",\"a\nb\",1"
+            ]
+        );
+        assert_eq!(
+            decode_record(records[0]).unwrap(),
+            ["Say \"hi", "desc", "100"]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_is_corrupt() {
+        assert!(decode_record("\"open,100").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn record_round_trip(fields in proptest::collection::vec(field_strategy(), 1..6)) {
+            let encoded = encode_record(&fields);
+            prop_assert_eq!(decode_record(&encoded).unwrap(), fields);
+        }
+
+        #[test]
+        fn document_round_trip(rows in proptest::collection::vec(
+            proptest::collection::vec(field_strategy(), 2..5), 0..6)
+        ) {
+            let document: String = rows
+                .iter()
+                .map(|row| encode_record(row) + "\n")
+                .collect();
+            let decoded: Vec<Vec<String>> = split_records(&document)
+                .into_iter()
+                .map(|record| decode_record(record).unwrap())
+                .collect();
+            prop_assert_eq!(decoded, rows);
+        }
+    }
+}
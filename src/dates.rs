@@ -0,0 +1,73 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+
+// The local calendar day unix time `timestamp` falls on.
+pub fn local_day(timestamp: i64) -> NaiveDate {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|time| time.date_naive())
+        .unwrap_or_default()
+}
+
+// Unix time of the first second of `day` in local time, or None if local time skips
+// that whole day.
+pub fn start_of_day(day: NaiveDate) -> Option<i64> {
+    day.and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|midnight| midnight.timestamp())
+}
+
+// The unix time `when` names, counted from `now`: a duration such as `3d` or `+12h`
+// (in m, h, d or w), a day as YYYY-MM-DD (its start), a local time as YYYY-MM-DDTHH:MM,
+// or `now`.
+pub fn parse_until(when: &str, now: i64) -> Option<i64> {
+    let when = when.trim();
+    if when == "now" {
+        return Some(now);
+    }
+    if let Ok(day) = NaiveDate::parse_from_str(when, "%Y-%m-%d") {
+        return start_of_day(day);
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(when, "%Y-%m-%dT%H:%M") {
+        return time
+            .and_local_timezone(Local)
+            .earliest()
+            .map(|time| time.timestamp());
+    }
+    let duration = when.strip_prefix('+').unwrap_or(when);
+    let unit = duration.chars().last()?;
+    let count: i64 = duration[..duration.len() - unit.len_utf8()].parse().ok()?;
+    let seconds = match unit {
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86_400,
+        'w' => 7 * 86_400,
+        _ => return None,
+    };
+    count
+        .checked_mul(seconds)
+        .filter(|seconds| *seconds >= 0)
+        .and_then(|seconds| now.checked_add(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_days_and_times_parse() {
+        let now = 1_800_000_000;
+        assert_eq!(parse_until("3d", now), Some(now + 3 * 86_400));
+        assert_eq!(parse_until("+12h", now), Some(now + 12 * 3600));
+        assert_eq!(parse_until("now", now), Some(now));
+        let day = NaiveDate::from_ymd_opt(2026, 11, 10).unwrap();
+        assert_eq!(parse_until("2026-11-10", now), start_of_day(day));
+        assert_eq!(
+            parse_until("2026-11-10T09:30", now),
+            start_of_day(day).map(|start| start + 9 * 3600 + 30 * 60)
+        );
+        for invalid in ["", "d", "3x", "-2d", "2026-13-01", "soon"] {
+            assert_eq!(parse_until(invalid, now), None, "{invalid}");
+        }
+    }
+}
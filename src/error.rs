@@ -7,11 +7,28 @@ pub enum HeapError {
     FileDoesNotExist,
     MissingArgument((String, String)),
     DoesNotTakeArg(String),
+    ConflictingArgs((String, String)),
+    InvalidArgument((String, String)),
     TagCannotBeEmpty,
     NoTaggedElements(String),
     TaskNotFound(String),
     TaskAlreadyExists(String),
     NoTasksOnHeap,
+    NoTasksReady,
+    DependencyCycle((String, String)),
+    SubtaskCycle((String, String)),
+    NothingToUndo,
+    NothingToRedo,
+    BackupNotFound(usize),
+    UnsupportedSchema(u32),
+    InvalidConfig(String),
+    HeapLocked(std::path::PathBuf),
+    InvalidHeapName(String),
+    HeapNotFound(String),
+    HeapAlreadyExists(String),
+    HeapInUse(String),
+    #[cfg(feature = "sqlite")]
+    SqliteError(rusqlite::Error),
 }
 impl fmt::Display for HeapError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -25,11 +42,53 @@ impl fmt::Display for HeapError {
             FileDoesNotExist => write!(f, "File does not exist"),
             MissingArgument((arg, cmd)) => write!(f, "A {arg} is required for --{cmd}."),
             DoesNotTakeArg(str) => write!(f, "--{str} does not take arguments."),
+            InvalidArgument((arg, cmd)) => write!(f, "\"{arg}\" is not valid for --{cmd}."),
+            ConflictingArgs((first, second)) => {
+                write!(f, "--{first} cannot be combined with --{second}.")
+            }
             TagCannotBeEmpty => writeln!(f, "Tag cannot be empty or contain whitespace."),
             TaskNotFound(name) => writeln!(f, "Task \"{name}\" was not found."),
             TaskAlreadyExists(name) => writeln!(f, "Task \"{name}\" already exists."),
             NoTaggedElements(tag) => writeln!(f, "No elements found where tags {tag} intersect."),
             NoTasksOnHeap => writeln!(f, "No tasks found in the heap."),
+            NoTasksReady => writeln!(
+                f,
+                "No matching task is ready: they are snoozed, not due yet, waiting on other tasks or weigh nothing."
+            ),
+            DependencyCycle((task, prerequisite)) => writeln!(
+                f,
+                "Task \"{task}\" cannot come after \"{prerequisite}\": \"{prerequisite}\" already comes after it."
+            ),
+            SubtaskCycle((task, parent)) => writeln!(
+                f,
+                "Task \"{task}\" cannot be a subtask of \"{parent}\": \"{parent}\" is already part of it."
+            ),
+            NothingToUndo => writeln!(f, "There is nothing to undo."),
+            NothingToRedo => writeln!(f, "There is nothing to redo."),
+            BackupNotFound(number) => writeln!(f, "There is no backup number {number}."),
+            UnsupportedSchema(version) => writeln!(
+                f,
+                "The task heap uses schema v{version}, newer than the supported v{}. Please update task-heap.",
+                crate::storage::SCHEMA_VERSION
+            ),
+            InvalidConfig(reason) => writeln!(f, "Invalid configuration: {reason}."),
+            HeapLocked(path) => writeln!(
+                f,
+                "Another task-heap process is using the heap ({}). Try again once it finishes.",
+                path.display()
+            ),
+            InvalidHeapName(name) => writeln!(
+                f,
+                "\"{name}\" is not a valid heap name. Use letters, digits, '-' and '_' (and not \"db\")."
+            ),
+            HeapNotFound(name) => writeln!(f, "Heap \"{name}\" was not found."),
+            HeapAlreadyExists(name) => writeln!(f, "Heap \"{name}\" already exists."),
+            HeapInUse(name) => writeln!(
+                f,
+                "Heap \"{name}\" is in use by this command. Select another heap with --heap."
+            ),
+            #[cfg(feature = "sqlite")]
+            SqliteError(e) => write!(f, "Database Error: {}", e),
         }
     }
 }
@@ -38,3 +97,9 @@ impl From<std::io::Error> for HeapError {
         HeapError::FileError(err)
     }
 }
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for HeapError {
+    fn from(err: rusqlite::Error) -> HeapError {
+        HeapError::SqliteError(err)
+    }
+}
@@ -0,0 +1,1184 @@
+use crate::{
+    Hash, HeapError, Task, Tasks,
+    aging::Aging,
+    backup::{Backups, Snapshot},
+    chicken::ChickenPolicy,
+    completion::CompletionLog,
+    journal::{Change, HistoryLine, Journal, POP_SUMMARY},
+    selection::{Candidate, SelectionStrategy},
+    stats::Stats,
+    storage::Storage,
+};
+use chrono::{Local, Utc};
+use rand::RngCore;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+// One loaded heap. Every mutation goes to the storage and the journal as it happens;
+// `save` writes the heap back and must be called once the caller is done with it.
+pub struct TaskHeap {
+    tasks: Tasks,
+    storage: Box<dyn Storage>,
+    journal: Journal,
+    completions: CompletionLog,
+    backups: Backups,
+    weighting: Weighting,
+    is_new: bool,
+    // The ID the next pushed task gets. IDs are never handed out twice, not even
+    // those of tasks only the journal still holds.
+    next_id: u32,
+}
+impl TaskHeap {
+    pub fn open(
+        mut storage: Box<dyn Storage>,
+        journal: Journal,
+        backups: Backups,
+    ) -> Result<Self, HeapError> {
+        // Only a missing database starts a new heap; anything else (corrupt rows, a newer
+        // schema) must not be overwritten by the next save.
+        let (tasks, is_new) = match storage.load() {
+            Ok(tasks) => (tasks, false),
+            Err(HeapError::FileDoesNotExist) => (HashMap::new(), true),
+            Err(err) => return Err(err),
+        };
+        let next_id = tasks
+            .values()
+            .chain(journal.tasks())
+            .map(Task::get_id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let mut heap = Self {
+            tasks,
+            storage,
+            journal,
+            completions: CompletionLog::in_memory(),
+            backups,
+            weighting: Weighting::default(),
+            is_new,
+            next_id,
+        };
+        heap.number_tasks()?;
+        Ok(heap)
+    }
+    pub fn with_weighting(mut self, weighting: Weighting) -> Self {
+        self.weighting = weighting;
+        self
+    }
+    pub fn with_completions(mut self, completions: CompletionLog) -> Self {
+        self.completions = completions;
+        self
+    }
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+    // True when there was no database yet, so saving will create it.
+    pub fn is_new(&self) -> bool {
+        self.is_new
+    }
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+    pub fn get(&self, name: &str) -> Option<&Task> {
+        self.tasks.get(&Task::hash_fn(name))
+    }
+    // A task by name or, when no task has that name, by ID.
+    pub fn find(&self, name_or_id: &str) -> Option<&Task> {
+        self.get(name_or_id).or_else(|| {
+            let id: u32 = name_or_id.parse().ok()?;
+            self.tasks.values().find(|task| task.get_id() == id)
+        })
+    }
+    // Names of the tasks still waiting on a prerequisite, which sit out of pops.
+    pub fn blocked(&self) -> HashSet<String> {
+        self.tasks
+            .values()
+            .filter(|task| is_blocked(&self.tasks, task))
+            .map(|task| task.get_name().to_owned())
+            .collect()
+    }
+    // Tasks holding all of `tags`, or every task when `tags` is empty, sorted by name
+    // so a seeded pick does not depend on the map's iteration order.
+    pub fn query(&mut self, tags: &[String]) -> Result<Vec<&Task>, HeapError> {
+        let hashes = self.storage.find_by_tags(&self.tasks, tags)?;
+        Ok(sorted_by_name(&self.tasks, hashes))
+    }
+    // Like `query`, but finding nothing is an error.
+    pub fn list(&mut self, tags: &[String]) -> Result<Vec<&Task>, HeapError> {
+        let tasks = self.query(tags)?;
+        if tasks.is_empty() {
+            return Err(nothing_found(tags));
+        }
+        Ok(tasks)
+    }
+    // Gives the task the next ID, whatever ID it had.
+    pub fn push(&mut self, mut task: Task) -> Result<(), HeapError> {
+        let hash = task.get_hash();
+        if self.tasks.contains_key(&hash) {
+            return Err(HeapError::TaskAlreadyExists(task.get_name().to_owned()));
+        }
+        check_prerequisites(&self.tasks, task.get_name(), &task.get_prerequisites())?;
+        if let Some(parent) = task.get_parent() {
+            check_parent(&self.tasks, task.get_name(), parent)?;
+        }
+        task.set_id(self.next_id);
+        self.storage.insert(&task)?;
+        self.journal.record(
+            format!("push \"{}\"", task.get_name()),
+            vec![Change::new(None, Some(task.clone()))],
+        );
+        self.tasks.insert(hash, task);
+        self.next_id += 1;
+        Ok(())
+    }
+    // Chooses a task matching `tags` with `strategy`, without removing it. Tasks compete
+    // with their effective weight, and those cooling down sit out unless nothing else
+    // matches. A task with subtasks is done through them, so the choice goes on among
+    // its ready subtasks the same way.
+    pub fn pick(
+        &mut self,
+        tags: &[String],
+        strategy: &dyn SelectionStrategy,
+        rng: &mut dyn RngCore,
+    ) -> Result<&Task, HeapError> {
+        let picked = self.pick_many(tags, 1, strategy, rng)?;
+        Ok(picked[0])
+    }
+    // Like `pick`, but draws up to `count` distinct tasks, each one from the tasks not
+    // drawn yet. Fewer come back when fewer match.
+    pub fn pick_many(
+        &mut self,
+        tags: &[String],
+        count: usize,
+        strategy: &dyn SelectionStrategy,
+        rng: &mut dyn RngCore,
+    ) -> Result<Vec<&Task>, HeapError> {
+        let hashes = self.storage.find_by_tags(&self.tasks, tags)?;
+        let hierarchy = Hierarchy::new(&self.tasks, self.weighting, Utc::now().timestamp());
+        let mut candidates = candidates(&hierarchy, hashes, tags)?;
+        let mut previous = self.journal.last_popped();
+        let mut picked = Vec::new();
+        while picked.len() < count && !candidates.is_empty() {
+            // Once something is drawn, candidates that weigh nothing just end the draw.
+            let index = match strategy.choose(&candidates, previous, rng) {
+                Ok(index) => index,
+                Err(HeapError::NoTasksReady) if !picked.is_empty() => break,
+                Err(err) => return Err(err),
+            };
+            let mut task = candidates.remove(index).task;
+            loop {
+                let subtasks = hierarchy.compete(hierarchy.children(task));
+                if subtasks.is_empty() {
+                    break;
+                }
+                task = subtasks[strategy.choose(&subtasks, previous, rng)?].task;
+            }
+            previous = Some(task);
+            picked.push(task);
+        }
+        Ok(picked)
+    }
+    // The tasks `pick` could offer, each with its chance of being offered, most likely
+    // first. A task with subtasks shares its chance out among them.
+    pub fn odds(
+        &mut self,
+        tags: &[String],
+        strategy: &dyn SelectionStrategy,
+    ) -> Result<Vec<(Candidate<'_>, f64)>, HeapError> {
+        let hashes = self.storage.find_by_tags(&self.tasks, tags)?;
+        let hierarchy = Hierarchy::new(&self.tasks, self.weighting, Utc::now().timestamp());
+        let candidates = candidates(&hierarchy, hashes, tags)?;
+        let mut odds = Vec::new();
+        hierarchy.share(
+            strategy,
+            self.journal.last_popped(),
+            candidates,
+            1.0,
+            &mut odds,
+        );
+        // Candidates come in name order, and the sort is stable.
+        odds.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Ok(odds)
+    }
+    // Removes a task because it is being done, moving it to the completion log and
+    // unblocking the tasks waiting on it. A recurring task stays instead, hidden until
+    // its next occurrence is due. Popping the last subtask of a task completes that
+    // task too, and so on up. Returns the tasks as they were done, the popped one first.
+    pub fn pop(&mut self, name: &str) -> Result<Vec<Task>, HeapError> {
+        let Some(task) = self.get(name).cloned() else {
+            return Err(HeapError::TaskNotFound(name.to_owned()));
+        };
+        let now = Utc::now().timestamp();
+        let mut done = vec![task];
+        while let Some(last) = done.last().filter(|task| task.get_recurrence().is_none()) {
+            let Some(parent) = parent_of(&self.tasks, last) else {
+                break;
+            };
+            let open = self.tasks.values().any(|task| {
+                task.get_parent() == Some(parent.get_name()) && task.get_name() != last.get_name()
+            });
+            if open {
+                break;
+            }
+            done.push(parent.clone());
+        }
+        // The popped task's own change comes first, see `Journal::last_popped`.
+        let mut changes: Vec<Change> = done
+            .iter()
+            .map(|task| Change::new(Some(task.clone()), next_occurrence(task, now)))
+            .collect();
+        let renames: Vec<(&str, Option<&str>)> =
+            done.iter().map(|task| (task.get_name(), None)).collect();
+        changes.extend(self.relink(&renames));
+        self.commit(format!("{POP_SUMMARY}\"{name}\""), changes)?;
+        for task in &done {
+            self.completions.record(task.clone(), now);
+        }
+        Ok(done)
+    }
+    // Records that a picked task was turned down, applying the chicken policy.
+    pub fn reject(&mut self, name: &str) -> Result<&Task, HeapError> {
+        let cooldown_until = self
+            .weighting
+            .chicken
+            .cooldown_until(Utc::now().timestamp());
+        self.modify(name, format!("chicken out of \"{name}\""), |task| {
+            task.set_rejections(task.get_rejections().saturating_add(1));
+            if let Some(cooldown_until) = cooldown_until {
+                task.set_cooldown_until(cooldown_until);
+            }
+            Ok(())
+        })
+    }
+    // Hides a task from pops until unix time `until`; a time already past wakes it.
+    pub fn snooze(&mut self, name: &str, until: i64) -> Result<&Task, HeapError> {
+        self.modify(name, format!("snooze \"{name}\""), |task| {
+            task.set_hidden_until(until);
+            Ok(())
+        })
+    }
+    pub fn delete(&mut self, name: &str) -> Result<Task, HeapError> {
+        self.remove(name, "delete ")
+    }
+    // Removes every task holding all of `tags`, after taking a backup. Also returns
+    // where the backup went, if backups are on.
+    pub fn delete_tagged(
+        &mut self,
+        tags: &[String],
+    ) -> Result<(Vec<Task>, Option<PathBuf>), HeapError> {
+        let hashes = self.storage.find_by_tags(&self.tasks, tags)?;
+        if hashes.is_empty() {
+            return Err(nothing_found(tags));
+        }
+        let backup = self.backups.snapshot(&self.tasks, "delete")?;
+        let deleted: Vec<Task> = sorted_by_name(&self.tasks, hashes)
+            .into_iter()
+            .cloned()
+            .collect();
+        let renames: Vec<(&str, Option<&str>)> =
+            deleted.iter().map(|task| (task.get_name(), None)).collect();
+        let changes = deleted
+            .iter()
+            .map(|task| Change::new(Some(task.clone()), None))
+            .chain(self.relink(&renames))
+            .collect();
+        self.commit(format!("delete tasks tagged {}", tags.join(",")), changes)?;
+        Ok((deleted, backup))
+    }
+    // Applies `edit` to a copy of the task, so a rename onto an existing task leaves
+    // the heap untouched.
+    // Nothing changes when `edit` fails.
+    pub fn edit<F>(&mut self, name: &str, edit: F) -> Result<&Task, HeapError>
+    where
+        F: FnOnce(&mut Task) -> Result<(), HeapError>,
+    {
+        self.modify(name, format!("edit \"{name}\""), edit)
+    }
+    pub fn clear_tags(&mut self, name: &str) -> Result<&Task, HeapError> {
+        self.modify(name, format!("clear tags of \"{name}\""), |task| {
+            task.clear_tags();
+            Ok(())
+        })
+    }
+    // Removes every task, after taking a backup.
+    pub fn reset(&mut self) -> Result<Option<PathBuf>, HeapError> {
+        let backup = self.backups.snapshot(&self.tasks, "reset")?;
+        self.storage.clear()?;
+        let changes = self
+            .tasks
+            .drain()
+            .map(|(_, task)| Change::new(Some(task), None))
+            .collect();
+        self.journal.record("reset", changes);
+        Ok(backup)
+    }
+    // Returns the summaries of the undone operations, most recent first.
+    pub fn undo(&mut self, count: usize) -> Result<Vec<String>, HeapError> {
+        let undone = self.journal.undo(count)?;
+        self.replay(undone, true)
+    }
+    pub fn redo(&mut self, count: usize) -> Result<Vec<String>, HeapError> {
+        let redone = self.journal.redo(count)?;
+        self.replay(redone, false)
+    }
+    pub fn history(&self, count: usize) -> Vec<HistoryLine> {
+        self.journal.history(count)
+    }
+    pub fn completions(&self) -> &CompletionLog {
+        &self.completions
+    }
+    pub fn stats(&self) -> Stats {
+        let open: Vec<&Task> = self.tasks.values().collect();
+        Stats::compute(self.completions.all(), &open, Local::now().date_naive())
+    }
+    pub fn backups(&self) -> &Backups {
+        &self.backups
+    }
+    // Replaces every task with the snapshot's, after backing up the current ones.
+    // Restoring is journaled, so it can be undone.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<Option<PathBuf>, HeapError> {
+        let restored = self.backups.load(snapshot)?;
+        let backup = self.backups.snapshot(&self.tasks, "restore")?;
+        let changes: Vec<Change> = self
+            .tasks
+            .values()
+            .map(|task| Change::new(Some(task.clone()), None))
+            .chain(
+                restored
+                    .into_values()
+                    .map(|task| Change::new(None, Some(task))),
+            )
+            .collect();
+        self.commit(
+            format!("restore backup from {}", snapshot.timestamp),
+            changes,
+        )?;
+        self.number_tasks()?;
+        Ok(backup)
+    }
+    // The journal and completions are only written once the heap itself is safely written.
+    pub fn save(&mut self) -> Result<(), HeapError> {
+        self.storage.save(&self.tasks)?;
+        self.is_new = false;
+        self.journal.save()?;
+        self.completions.save()
+    }
+    // `action` starts the journal summary.
+    fn remove(&mut self, name: &str, action: &str) -> Result<Task, HeapError> {
+        let Some(task) = self.get(name).cloned() else {
+            return Err(HeapError::TaskNotFound(name.to_owned()));
+        };
+        let mut changes = vec![Change::new(Some(task.clone()), None)];
+        changes.extend(self.relink(&[(name, None)]));
+        self.commit(format!("{action}\"{name}\""), changes)?;
+        Ok(task)
+    }
+    fn modify<F>(&mut self, name: &str, summary: String, edit: F) -> Result<&Task, HeapError>
+    where
+        F: FnOnce(&mut Task) -> Result<(), HeapError>,
+    {
+        let hash = Task::hash_fn(name);
+        let Some(before) = self.tasks.get(&hash) else {
+            return Err(HeapError::TaskNotFound(name.to_owned()));
+        };
+        let mut task = before.clone();
+        edit(&mut task)?;
+        let new_hash = task.get_hash();
+        if new_hash != hash && self.tasks.contains_key(&new_hash) {
+            return Err(HeapError::TaskAlreadyExists(task.get_name().to_owned()));
+        }
+        let known = before.get_prerequisites();
+        let added: Vec<&str> = task
+            .get_prerequisites()
+            .into_iter()
+            .filter(|prerequisite| !known.contains(prerequisite))
+            .collect();
+        check_prerequisites(&self.tasks, name, &added)?;
+        if let Some(parent) = task.get_parent()
+            && task.get_parent() != before.get_parent()
+        {
+            check_parent(&self.tasks, name, parent)?;
+        }
+        let mut changes = vec![Change::new(Some(before.clone()), Some(task.clone()))];
+        if new_hash != hash {
+            changes.extend(self.relink(&[(name, Some(task.get_name()))]));
+        }
+        self.commit(summary, changes)?;
+        Ok(&self.tasks[&new_hash])
+    }
+    // Points the prerequisites and parents naming an `old` task at its new name, or
+    // drops them when there is none, so its subtasks become top-level. Returns the
+    // changes to the tasks that named it.
+    fn relink(&self, renames: &[(&str, Option<&str>)]) -> Vec<Change> {
+        let hashes = self.tasks.keys().copied().collect();
+        let mut changes = Vec::new();
+        for task in sorted_by_name(&self.tasks, hashes) {
+            if renames.iter().any(|(old, _)| *old == task.get_name()) {
+                continue;
+            }
+            let mut relinked = task.clone();
+            let mut changed = false;
+            for (old, new) in renames {
+                if relinked.remove_prerequisite(old) {
+                    changed = true;
+                    if let Some(new) = new {
+                        relinked.add_prerequisite(*new);
+                    }
+                }
+                if relinked.get_parent() == Some(*old) {
+                    changed = true;
+                    relinked.set_parent(new.map(str::to_owned));
+                }
+            }
+            if changed {
+                changes.push(Change::new(Some(task.clone()), Some(relinked)));
+            }
+        }
+        changes
+    }
+    fn commit(&mut self, summary: String, changes: Vec<Change>) -> Result<(), HeapError> {
+        for change in &changes {
+            change.apply(&mut self.tasks, self.storage.as_mut())?;
+        }
+        self.journal.record(summary, changes);
+        Ok(())
+    }
+    fn replay(
+        &mut self,
+        entries: Vec<(String, Vec<Change>)>,
+        undoing: bool,
+    ) -> Result<Vec<String>, HeapError> {
+        let mut summaries = Vec::new();
+        for (summary, changes) in entries {
+            for change in &changes {
+                change.apply(&mut self.tasks, self.storage.as_mut())?;
+            }
+            // Undoing a pop takes its completions back, redoing it completes the tasks
+            // again.
+            if summary.starts_with(POP_SUMMARY) {
+                for task in completed_by(&changes, undoing) {
+                    if undoing {
+                        self.completions.retract(task.get_name());
+                    } else {
+                        self.completions
+                            .record(task.clone(), Utc::now().timestamp());
+                    }
+                }
+            }
+            summaries.push(summary);
+        }
+        self.number_tasks()?;
+        Ok(summaries)
+    }
+    // Gives an ID to the tasks from before there were IDs, in name order. They can
+    // come from an older database, backup or journal.
+    fn number_tasks(&mut self) -> Result<(), HeapError> {
+        let hashes = self
+            .tasks
+            .iter()
+            .filter(|(_, task)| task.get_id() == 0)
+            .map(|(hash, _)| *hash)
+            .collect();
+        let names: Vec<String> = sorted_by_name(&self.tasks, hashes)
+            .into_iter()
+            .map(|task| task.get_name().to_owned())
+            .collect();
+        for name in names {
+            let hash = Task::hash_fn(&name);
+            if let Some(task) = self.tasks.get_mut(&hash) {
+                task.set_id(self.next_id);
+                self.storage.update(&hash, task)?;
+                self.next_id += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+// How a task's base weight becomes the weight it competes with in a pop.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Weighting {
+    pub aging: Aging,
+    pub chicken: ChickenPolicy,
+}
+impl Weighting {
+    pub fn effective_weight(&self, task: &Task, now: i64) -> u32 {
+        let aged = self.aging.effective_weight(task, now);
+        let weight = (aged as f64 * self.chicken.weight_factor(task.get_rejections()))
+            .round()
+            .min(u32::MAX as f64) as u32;
+        // Decay slows a task down but never takes it off the heap.
+        if task.get_weight() > 0 {
+            weight.max(1)
+        } else {
+            weight
+        }
+    }
+}
+
+fn sorted_by_name(tasks: &Tasks, hashes: Vec<Hash>) -> Vec<&Task> {
+    let mut tasks: Vec<&Task> = hashes
+        .into_iter()
+        .filter_map(|hash| tasks.get(&hash))
+        .collect();
+    tasks.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+    tasks
+}
+
+// The tasks matching `tags` that a pick starts from. Subtasks are reached through
+// their parent when it matches too.
+fn candidates<'a>(
+    hierarchy: &Hierarchy<'a>,
+    hashes: Vec<Hash>,
+    tags: &[String],
+) -> Result<Vec<Candidate<'a>>, HeapError> {
+    let tasks = sorted_by_name(hierarchy.tasks, hashes);
+    if tasks.is_empty() {
+        return Err(nothing_found(tags));
+    }
+    let matching: HashSet<&str> = tasks.iter().map(|task| task.get_name()).collect();
+    let candidates = hierarchy.compete(
+        tasks
+            .into_iter()
+            .filter(|task| {
+                !ancestors(hierarchy.tasks, task).any(|parent| matching.contains(parent.get_name()))
+            })
+            .collect(),
+    );
+    if candidates.is_empty() {
+        return Err(HeapError::NoTasksReady);
+    }
+    Ok(candidates)
+}
+
+// The subtasks of every task on the heap, for picks to descend into.
+struct Hierarchy<'a> {
+    tasks: &'a Tasks,
+    children: HashMap<&'a str, Vec<&'a Task>>,
+    weighting: Weighting,
+    now: i64,
+}
+impl<'a> Hierarchy<'a> {
+    fn new(tasks: &'a Tasks, weighting: Weighting, now: i64) -> Self {
+        let mut children: HashMap<&str, Vec<&Task>> = HashMap::new();
+        for task in sorted_by_name(tasks, tasks.keys().copied().collect()) {
+            if let Some(parent) = parent_of(tasks, task) {
+                children.entry(parent.get_name()).or_default().push(task);
+            }
+        }
+        Self {
+            tasks,
+            children,
+            weighting,
+            now,
+        }
+    }
+    // Sorted by name.
+    fn children(&self, task: &Task) -> Vec<&'a Task> {
+        self.children
+            .get(task.get_name())
+            .cloned()
+            .unwrap_or_default()
+    }
+    // Tasks that are not due yet or still wait on a prerequisite never take part, nor
+    // do tasks none of whose subtasks can.
+    fn is_ready(&self, task: &Task) -> bool {
+        let children = self.children(task);
+        !task.is_hidden(self.now)
+            && !is_blocked(self.tasks, task)
+            && (children.is_empty() || children.iter().any(|child| self.is_ready(child)))
+    }
+    // The ready ones of `tasks`, with their effective weight. Tasks cooling down sit
+    // out unless nothing else is ready.
+    fn compete(&self, mut tasks: Vec<&'a Task>) -> Vec<Candidate<'a>> {
+        tasks.retain(|task| self.is_ready(task));
+        if tasks.iter().any(|task| !task.is_cooling_down(self.now)) {
+            tasks.retain(|task| !task.is_cooling_down(self.now));
+        }
+        tasks
+            .into_iter()
+            .map(|task| Candidate {
+                task,
+                weight: self.weighting.effective_weight(task, self.now),
+            })
+            .collect()
+    }
+    // Shares `chance` out among `candidates` the way `strategy` picks, passing the
+    // share of a task with ready subtasks on to them.
+    fn share(
+        &self,
+        strategy: &dyn SelectionStrategy,
+        previous: Option<&Task>,
+        candidates: Vec<Candidate<'a>>,
+        chance: f64,
+        odds: &mut Vec<(Candidate<'a>, f64)>,
+    ) {
+        let probabilities = strategy.probabilities(&candidates, previous);
+        for (candidate, probability) in candidates.into_iter().zip(probabilities) {
+            let subtasks = self.compete(self.children(candidate.task));
+            if subtasks.is_empty() {
+                odds.push((candidate, chance * probability));
+            } else {
+                self.share(strategy, previous, subtasks, chance * probability, odds);
+            }
+        }
+    }
+}
+
+fn parent_of<'a>(tasks: &'a Tasks, task: &Task) -> Option<&'a Task> {
+    task.get_parent()
+        .and_then(|parent| tasks.get(&Task::hash_fn(parent)))
+}
+
+// The task's parent, that one's parent and so on, as long as they are on the heap.
+fn ancestors<'a>(tasks: &'a Tasks, task: &Task) -> impl Iterator<Item = &'a Task> {
+    std::iter::successors(parent_of(tasks, task), |task| parent_of(tasks, task)).take(tasks.len())
+}
+
+// How a done task comes back, if it recurs: afresh, aging from when it is due.
+fn next_occurrence(task: &Task, now: i64) -> Option<Task> {
+    let due = task.get_recurrence()?.next_due(now);
+    let mut next = task.clone();
+    next.set_hidden_until(due)
+        .set_created(due)
+        .set_rejections(0)
+        .set_cooldown_until(0);
+    Some(next)
+}
+
+// The tasks a pop's journal entry completed, as they were when done. They left the
+// heap or, recurring, came back afresh; the tasks it relinked keep their creation
+// time. `undoing` entries hold the changes inverted.
+fn completed_by(changes: &[Change], undoing: bool) -> Vec<&Task> {
+    changes
+        .iter()
+        .filter_map(|change| {
+            let (done, next) = match undoing {
+                true => (change.after()?, change.before()),
+                false => (change.before()?, change.after()),
+            };
+            match next {
+                Some(next) if next.get_created() == done.get_created() => None,
+                _ => Some(done),
+            }
+        })
+        .collect()
+}
+
+// A prerequisite leaves the heap when it is popped or deleted, so any that is still
+// there blocks the task.
+fn is_blocked(tasks: &Tasks, task: &Task) -> bool {
+    task.get_prerequisites()
+        .into_iter()
+        .any(|prerequisite| tasks.contains_key(&Task::hash_fn(prerequisite)))
+}
+
+// `added` prerequisites of the task `name` must be on the heap, and none of them may
+// already come after the task, directly or through others.
+fn check_prerequisites(tasks: &Tasks, name: &str, added: &[&str]) -> Result<(), HeapError> {
+    for prerequisite in added {
+        let Some(task) = tasks.get(&Task::hash_fn(prerequisite)) else {
+            return Err(HeapError::TaskNotFound((*prerequisite).to_owned()));
+        };
+        let mut seen = HashSet::new();
+        let mut pending = vec![task];
+        while let Some(task) = pending.pop() {
+            if task.get_name() == name {
+                return Err(HeapError::DependencyCycle((
+                    name.to_owned(),
+                    (*prerequisite).to_owned(),
+                )));
+            }
+            if seen.insert(task.get_name()) {
+                pending.extend(
+                    task.get_prerequisites()
+                        .into_iter()
+                        .filter_map(|before| tasks.get(&Task::hash_fn(before))),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+// The parent of the task `name` must be on the heap, and must not be the task itself
+// or one of its subtasks.
+fn check_parent(tasks: &Tasks, name: &str, parent: &str) -> Result<(), HeapError> {
+    let Some(task) = tasks.get(&Task::hash_fn(parent)) else {
+        return Err(HeapError::TaskNotFound(parent.to_owned()));
+    };
+    if std::iter::once(task)
+        .chain(ancestors(tasks, task))
+        .any(|task| task.get_name() == name)
+    {
+        return Err(HeapError::SubtaskCycle((
+            name.to_owned(),
+            parent.to_owned(),
+        )));
+    }
+    Ok(())
+}
+
+// Fails the way `TaskHeap::pick` would when the tasks matching `tags` leave nothing to
+// pick. It needs no open heap, so a meta-pop can look at heaps it is not popping from.
+pub(crate) fn check_ready(
+    tasks: &Tasks,
+    weighting: Weighting,
+    tags: &[String],
+) -> Result<(), HeapError> {
+    let hashes = tasks
+        .iter()
+        .filter(|(_, task)| task.has_tags(tags))
+        .map(|(hash, _)| *hash)
+        .collect();
+    let hierarchy = Hierarchy::new(tasks, weighting, Utc::now().timestamp());
+    candidates(&hierarchy, hashes, tags).map(|_| ())
+}
+
+fn nothing_found(tags: &[String]) -> HeapError {
+    if tags.is_empty() {
+        HeapError::NoTasksOnHeap
+    } else {
+        HeapError::NoTaggedElements(tags.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        recurrence::Recurrence,
+        selection::Selection,
+        storage::{MemoryStorage, Storage},
+    };
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn heap() -> TaskHeap {
+        TaskHeap::open(
+            Box::new(MemoryStorage::default()),
+            Journal::in_memory(),
+            Backups::disabled(),
+        )
+        .unwrap()
+        .with_weighting(Weighting {
+            aging: Aging::None,
+            chicken: ChickenPolicy::None,
+        })
+    }
+
+    #[test]
+    fn edits_and_undo_go_through_the_api() {
+        let mut heap = heap();
+        let mut task = Task::from_arg("Clean the garage");
+        task.set_weight("5").unwrap();
+        task.add_tags(vec!["home".to_owned()]);
+        heap.push(task).unwrap();
+        heap.push(Task::from_arg("Write tests")).unwrap();
+
+        heap.edit("Clean the garage", |task| {
+            task.set_name("Clean the house");
+            Ok(())
+        })
+        .unwrap();
+        heap.clear_tags("Clean the house").unwrap();
+        let task = heap.get("Clean the house").unwrap();
+        assert_eq!(task.get_weight(), 5);
+        assert!(task.get_tags().is_empty());
+        assert_eq!(heap.len(), 2);
+
+        assert_eq!(heap.undo(2).unwrap().len(), 2);
+        assert!(heap.get("Clean the house").is_none());
+        assert!(
+            heap.get("Clean the garage")
+                .unwrap()
+                .has_tags(&["home".to_owned()])
+        );
+    }
+
+    #[test]
+    fn conflicting_changes_leave_the_heap_untouched() {
+        let mut heap = heap();
+        heap.push(Task::from_arg("a")).unwrap();
+        heap.push(Task::from_arg("b")).unwrap();
+        assert!(matches!(
+            heap.push(Task::from_arg("a")),
+            Err(HeapError::TaskAlreadyExists(_))
+        ));
+        assert!(matches!(
+            heap.edit("a", |task| {
+                task.set_name("b");
+                Ok(())
+            }),
+            Err(HeapError::TaskAlreadyExists(_))
+        ));
+        assert!(heap.get("a").is_some());
+        assert_eq!(heap.len(), 2);
+    }
+
+    fn heap_of(names: &[&str]) -> TaskHeap {
+        let mut heap = heap();
+        for (index, name) in names.iter().enumerate() {
+            let mut task = Task::from_arg(*name);
+            task.set_weight((10 * (index + 1)).to_string()).unwrap();
+            heap.push(task).unwrap();
+        }
+        heap
+    }
+
+    fn seeded_picks(heap: &mut TaskHeap, seed: u64) -> Vec<String> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..8)
+            .map(|_| {
+                heap.pick(&[], &Selection::Linear, &mut rng)
+                    .unwrap()
+                    .get_name()
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_and_heap_pick_the_same_tasks() {
+        let names = ["laundry", "taxes", "groceries", "email", "gym"];
+        let mut forwards = heap_of(&names);
+        // Same tasks and weights, inserted in a different order.
+        let mut backwards = heap();
+        for name in names.iter().rev() {
+            backwards.push(forwards.get(name).unwrap().clone()).unwrap();
+        }
+        for seed in [0, 1, 42, u64::MAX] {
+            assert_eq!(
+                seeded_picks(&mut forwards, seed),
+                seeded_picks(&mut backwards, seed)
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_picks_match_snapshot() {
+        let mut heap = heap_of(&["laundry", "taxes", "groceries", "email", "gym"]);
+        assert_eq!(seeded_picks(&mut heap, 42), SNAPSHOT_SEED_42);
+    }
+    // Changes only if the weighting, the candidate order or the RNG changes.
+    const SNAPSHOT_SEED_42: [&str; 8] = [
+        "email",
+        "gym",
+        "email",
+        "gym",
+        "taxes",
+        "gym",
+        "taxes",
+        "groceries",
+    ];
+
+    #[test]
+    fn rejected_tasks_cool_down_or_change_weight() {
+        let mut heap = heap_of(&["a", "b"]).with_weighting(Weighting {
+            aging: Aging::None,
+            chicken: ChickenPolicy::Cooldown(1.0),
+        });
+        heap.reject("a").unwrap();
+        assert_eq!(heap.get("a").unwrap().get_rejections(), 1);
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let picked = heap.pick(&[], &Selection::Linear, &mut rng).unwrap();
+            assert_eq!(picked.get_name(), "b");
+        }
+        // With nothing else left, a task cooling down is still offered.
+        heap.reject("b").unwrap();
+        assert!(
+            heap.pick(&[], &Selection::Linear, &mut StdRng::seed_from_u64(0))
+                .is_ok()
+        );
+
+        let now = Utc::now().timestamp();
+        let a = heap.get("a").unwrap();
+        let decay = Weighting {
+            aging: Aging::None,
+            chicken: ChickenPolicy::Decay(0.5),
+        };
+        assert_eq!(decay.effective_weight(a, now), 5);
+        let boost = Weighting {
+            aging: Aging::None,
+            chicken: ChickenPolicy::Boost(2.0),
+        };
+        assert_eq!(boost.effective_weight(a, now), 20);
+    }
+
+    #[test]
+    fn pick_many_draws_distinct_tasks() {
+        let mut heap = heap_of(&["a", "b", "c", "d"]);
+        let mut rng = StdRng::seed_from_u64(3);
+        let picked = heap
+            .pick_many(&[], 3, &Selection::Linear, &mut rng)
+            .unwrap();
+        let mut names: Vec<&str> = picked.iter().map(|task| task.get_name()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 3);
+
+        let picked = heap
+            .pick_many(&[], 10, &Selection::Linear, &mut rng)
+            .unwrap();
+        assert_eq!(picked.len(), 4);
+    }
+
+    #[test]
+    fn popped_tasks_move_to_the_completion_log() {
+        let mut heap = heap_of(&["a", "b"]);
+        heap.reject("a").unwrap();
+        heap.pop("a").unwrap();
+        heap.pop("b").unwrap();
+        let completed = heap.completions().all();
+        assert_eq!(completed.len(), 2);
+        assert_eq!(completed[0].task.get_rejections(), 1);
+
+        heap.undo(1).unwrap();
+        assert_eq!(heap.completions().all().len(), 1);
+        assert!(heap.get("b").is_some());
+        heap.redo(1).unwrap();
+        assert_eq!(heap.completions().all()[1].task.get_name(), "b");
+    }
+
+    #[test]
+    fn recurring_tasks_come_back_once_due() {
+        let mut heap = heap_of(&["water plants", "taxes"]);
+        heap.edit("water plants", |task| {
+            task.set_recurrence(Some(Recurrence::Daily));
+            Ok(())
+        })
+        .unwrap();
+        heap.reject("water plants").unwrap();
+        heap.pop("water plants").unwrap();
+
+        let now = Utc::now().timestamp();
+        let plants = heap.get("water plants").unwrap();
+        assert!(plants.is_hidden(now));
+        assert_eq!(plants.get_rejections(), 0);
+        assert_eq!(heap.completions().all()[0].task.get_rejections(), 1);
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let picked = heap.pick(&[], &Selection::Linear, &mut rng).unwrap();
+            assert_eq!(picked.get_name(), "taxes");
+        }
+        heap.pop("taxes").unwrap();
+        assert!(matches!(
+            heap.pick(&[], &Selection::Linear, &mut rand::thread_rng()),
+            Err(HeapError::NoTasksReady)
+        ));
+
+        heap.undo(2).unwrap();
+        assert!(heap.completions().all().is_empty());
+        assert!(!heap.get("water plants").unwrap().is_hidden(now));
+        heap.redo(1).unwrap();
+        assert_eq!(heap.completions().all().len(), 1);
+        assert!(heap.get("water plants").unwrap().is_hidden(now));
+    }
+
+    #[test]
+    fn snoozed_tasks_wait_until_woken() {
+        let mut heap = heap_of(&["call Bob", "taxes"]);
+        let now = Utc::now().timestamp();
+        heap.snooze("call Bob", now + 3600).unwrap();
+        let odds = heap.odds(&[], &Selection::Linear).unwrap();
+        assert_eq!(odds.len(), 1);
+        assert_eq!(odds[0].0.task.get_name(), "taxes");
+
+        heap.snooze("call Bob", now).unwrap();
+        assert_eq!(heap.odds(&[], &Selection::Linear).unwrap().len(), 2);
+        heap.undo(1).unwrap();
+        assert!(heap.get("call Bob").unwrap().is_hidden(now));
+    }
+
+    #[test]
+    fn tasks_wait_for_their_prerequisites() {
+        let mut heap = heap_of(&["buy paint", "paint fence", "sand fence"]);
+        heap.edit("paint fence", |task| {
+            task.add_prerequisite("sand fence")
+                .add_prerequisite("buy paint");
+            Ok(())
+        })
+        .unwrap();
+        assert!(matches!(
+            heap.edit("buy paint", |task| {
+                task.add_prerequisite("paint fence");
+                Ok(())
+            }),
+            Err(HeapError::DependencyCycle(_))
+        ));
+        assert!(matches!(
+            heap.edit("sand fence", |task| {
+                task.add_prerequisite("sand fence");
+                Ok(())
+            }),
+            Err(HeapError::DependencyCycle(_))
+        ));
+        assert!(matches!(
+            heap.edit("sand fence", |task| {
+                task.add_prerequisite("prime fence");
+                Ok(())
+            }),
+            Err(HeapError::TaskNotFound(_))
+        ));
+        assert_eq!(heap.blocked().len(), 1);
+        let odds = heap.odds(&[], &Selection::Linear).unwrap();
+        assert!(odds.iter().all(|(c, _)| c.task.get_name() != "paint fence"));
+
+        // Renames follow through, popping and deleting unblock.
+        heap.edit("sand fence", |task| {
+            task.set_name("sand the fence");
+            Ok(())
+        })
+        .unwrap();
+        let waiting = heap.get("paint fence").unwrap().get_prerequisites();
+        assert_eq!(waiting, vec!["buy paint", "sand the fence"]);
+        heap.pop("sand the fence").unwrap();
+        heap.delete("buy paint").unwrap();
+        assert!(
+            heap.get("paint fence")
+                .unwrap()
+                .get_prerequisites()
+                .is_empty()
+        );
+        assert!(heap.blocked().is_empty());
+        assert!(
+            heap.pick(&[], &Selection::Linear, &mut StdRng::seed_from_u64(1))
+                .is_ok()
+        );
+
+        heap.undo(2).unwrap();
+        assert_eq!(heap.blocked().len(), 1);
+        assert!(heap.completions().all().is_empty());
+        heap.redo(1).unwrap();
+        assert_eq!(heap.completions().all().len(), 1);
+        heap.pop("buy paint").unwrap();
+        assert!(heap.blocked().is_empty());
+    }
+
+    #[test]
+    fn subtasks_are_popped_through_their_parent() {
+        let mut heap = heap_of(&["finish project", "write docs", "write tests", "taxes"]);
+        for name in ["write docs", "write tests"] {
+            heap.edit(name, |task| {
+                task.set_parent(Some("finish project".to_owned()));
+                Ok(())
+            })
+            .unwrap();
+        }
+        assert!(matches!(
+            heap.edit("finish project", |task| {
+                task.set_parent(Some("write docs".to_owned()));
+                Ok(())
+            }),
+            Err(HeapError::SubtaskCycle(_))
+        ));
+
+        // The parent's chance goes to its subtasks, by their weight.
+        let odds = heap.odds(&[], &Selection::Linear).unwrap();
+        let chances: Vec<(&str, f64)> = odds
+            .iter()
+            .map(|(candidate, chance)| (candidate.task.get_name(), *chance))
+            .collect();
+        assert_eq!(chances.len(), 3);
+        assert_eq!(chances[0].0, "taxes");
+        assert_eq!(chances[1].0, "write tests");
+        assert!((chances[1].1 - 0.2 * 30.0 / 50.0).abs() < 1e-9);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            let picked = heap.pick(&[], &Selection::Linear, &mut rng).unwrap();
+            assert_ne!(picked.get_name(), "finish project");
+        }
+
+        heap.pop("write docs").unwrap();
+        assert!(heap.get("finish project").is_some());
+        let done = heap.pop("write tests").unwrap();
+        assert_eq!(done.len(), 2);
+        assert!(heap.get("finish project").is_none());
+        assert_eq!(heap.completions().all().len(), 3);
+
+        heap.undo(1).unwrap();
+        assert_eq!(heap.completions().all().len(), 1);
+        assert_eq!(
+            heap.get("write tests").unwrap().get_parent(),
+            Some("finish project")
+        );
+        heap.redo(1).unwrap();
+        assert_eq!(heap.completions().all().len(), 3);
+    }
+
+    #[test]
+    fn ids_are_given_once_and_survive_renames() {
+        let mut storage = MemoryStorage::default();
+        let legacy: Tasks = ["walk dog", "feed cat"]
+            .into_iter()
+            .map(|name| (Task::hash_fn(name), Task::from_arg(name)))
+            .collect();
+        storage.save(&legacy).unwrap();
+        let mut heap =
+            TaskHeap::open(Box::new(storage), Journal::in_memory(), Backups::disabled()).unwrap();
+        assert_eq!(heap.get("feed cat").unwrap().get_id(), 1);
+        assert_eq!(heap.get("walk dog").unwrap().get_id(), 2);
+
+        heap.push(Task::from_arg("3")).unwrap();
+        heap.delete("3").unwrap();
+        heap.push(Task::from_arg("taxes")).unwrap();
+        assert_eq!(heap.get("taxes").unwrap().get_id(), 4);
+        heap.undo(2).unwrap();
+        assert_eq!(heap.get("3").unwrap().get_id(), 3);
+
+        heap.edit("walk dog", |task| {
+            task.set_name("walk the dog");
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(heap.find("2").unwrap().get_name(), "walk the dog");
+        // A name wins over an ID.
+        assert_eq!(heap.find("3").unwrap().get_name(), "3");
+        assert!(heap.find("4").is_none());
+    }
+
+    #[test]
+    fn odds_follow_the_pick_distribution() {
+        let mut heap = heap_of(&["a", "b", "c"]).with_weighting(Weighting {
+            aging: Aging::None,
+            chicken: ChickenPolicy::Cooldown(1.0),
+        });
+        let odds = heap.odds(&[], &Selection::Linear).unwrap();
+        let names: Vec<&str> = odds
+            .iter()
+            .map(|(candidate, _)| candidate.task.get_name())
+            .collect();
+        assert_eq!(names, ["c", "b", "a"]);
+        assert!((odds[0].1 - 0.5).abs() < 1e-9);
+
+        // A task cooling down is not offered, so it has no odds.
+        heap.reject("c").unwrap();
+        let odds = heap.odds(&[], &Selection::Linear).unwrap();
+        assert_eq!(odds.len(), 2);
+        assert!((odds[0].1 - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn empty_queries_are_errors_for_list_and_pick() {
+        let mut heap = heap();
+        assert!(matches!(heap.list(&[]), Err(HeapError::NoTasksOnHeap)));
+        heap.push(Task::from_arg("a")).unwrap();
+        let tags = ["work".to_owned()];
+        assert!(heap.query(&tags).unwrap().is_empty());
+        assert!(matches!(
+            heap.pick(&tags, &Selection::Linear, &mut rand::thread_rng()),
+            Err(HeapError::NoTaggedElements(_))
+        ));
+        assert_eq!(
+            heap.pick(&[], &Selection::Linear, &mut rand::thread_rng())
+                .unwrap()
+                .get_name(),
+            "a"
+        );
+    }
+}
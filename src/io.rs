@@ -1,103 +1,98 @@
-use crate::{HeapError, Task};
-use directories::ProjectDirs;
-use std::{
-    collections::HashMap,
-    env, fs,
-    io::{self, BufRead, BufReader, Write, stdin, stdout},
-    path::PathBuf,
-    str::FromStr,
+use task_heap::{
+    Task,
+    completion::Completion,
+    dates::{local_day, start_of_day},
+    journal::format_timestamp,
+    stats::Stats,
 };
 use terminal_size::{Width, terminal_size};
 use textwrap::wrap;
-fn get_db_path() -> PathBuf {
-    match env::var("TASK_HEAP_DBPATH") {
-        Ok(path) => PathBuf::from_str(&path).unwrap().join("./db.csv"),
-        Err(_) => {
-            if let Some(proj_dirs) = ProjectDirs::from("com", "tobe", "task-heap") {
-                // 2. Get the specific data directory (e.g., AppData/Roaming/task-heap)
-                let data_dir = proj_dirs.data_dir();
 
-                // 3. Create the directory if it doesn't exist (Crucial for first run!)
-                if !data_dir.exists() {
-                    fs::create_dir_all(data_dir).expect("Could not create data directory");
-                }
-
-                // 4. Append your filename
-                data_dir.join("./db.csv")
+// A task as listed: the base weight comes from the task, the aged one from the heap.
+// Subtasks listed under their parent are indented by `depth`.
+pub struct TaskRow<'a> {
+    pub task: &'a Task,
+    pub effective_weight: u32,
+    pub blocked: bool,
+    pub depth: usize,
+    pub now: i64,
+}
+impl TaskRow<'_> {
+    // How often the task recurs, until when it is hidden, what it waits on and what it
+    // is part of, e.g. "daily until 2026-10-19" or "after buy paint".
+    fn schedule(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(recurrence) = self.task.get_recurrence() {
+            parts.push(recurrence.to_string());
+        }
+        if self.task.is_hidden(self.now) {
+            // Snoozing by the hour ends partway through a day, so show the time too.
+            let until = self.task.get_hidden_until();
+            let day = local_day(until);
+            if start_of_day(day) == Some(until) {
+                parts.push(format!("until {day}"));
             } else {
-                PathBuf::from("./db.csv")
+                parts.push(format!("until {}", format_timestamp(until)));
             }
         }
-    }
-}
-pub fn write_task_heap(heap: HashMap<[u8; 32], Task>) -> std::io::Result<()> {
-    let db_path = get_db_path();
-    let db_file: fs::File = fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(db_path)?;
-    for (_, task) in heap {
-        match writeln!(&db_file, "{}", task) {
-            Ok(()) => (),
-            Err(e) => {
-                println!("File write error: {e}")
-            }
+        let prerequisites = self.task.get_prerequisites();
+        if !prerequisites.is_empty() {
+            parts.push(format!("after {}", prerequisites.join(", ")));
         }
-    }
-    Ok(())
-}
-pub fn read_task_heap() -> Result<HashMap<[u8; 32], Task>, HeapError> {
-    // Your code here
-    let db_path = get_db_path();
-    if db_path.exists() {
-        let file: fs::File = fs::OpenOptions::new().read(true).open(db_path)?;
-        let reader = BufReader::new(file);
-        let mut heap = HashMap::new();
-        for line in reader.lines() {
-            let csv_line = match line {
-                Ok(line) => line,
-                Err(e) => {
-                    return Err(HeapError::FileError(e));
-                }
-            };
-            let new_task: Task = csv_line.parse()?;
-            heap.insert(new_task.get_hash(), new_task);
+        // Unless it is listed under its parent already.
+        if let Some(parent) = self.task.get_parent().filter(|_| self.depth == 0) {
+            parts.push(format!("in {parent}"));
         }
-        Ok(heap)
-    } else {
-        Err(HeapError::FileDoesNotExist)
+        parts.join(" ")
     }
 }
-pub fn print_task_table(tasks: &Vec<&Task>) {
+
+pub fn print_task_table(rows: &[TaskRow]) {
     let term_width = if let Some((Width(w), _)) = terminal_size() {
         w as usize
     } else {
         80
     };
 
+    const W_ID: usize = 4;
     const W_WEIGHT: usize = 6;
+    const W_CHICKEN: usize = 7;
+    const W_READY: usize = 7;
     const W_TAGS: usize = 20;
-    const BORDER_OVERHEAD: usize = 13;
+    const W_SCHEDULE: usize = 16;
+    const BORDER_OVERHEAD: usize = 28;
 
-    let remaining_width = term_width.saturating_sub(W_WEIGHT + W_TAGS + BORDER_OVERHEAD);
+    let remaining_width = term_width.saturating_sub(
+        W_ID + 2 * W_WEIGHT + W_CHICKEN + W_READY + W_TAGS + W_SCHEDULE + BORDER_OVERHEAD,
+    );
     const RATIO_NAME_DESC: f64 = 0.3;
     let w_name = (remaining_width as f64 * RATIO_NAME_DESC) as usize;
     let w_description = (remaining_width as f64 * (1. - RATIO_NAME_DESC)) as usize;
     let w_name = w_name.max(5);
     let w_description = w_description.max(10);
     println!(
-        "{:<n$} | {:<d$} | {:>w$} | {:<t$}",
+        "{:>i$} | {:<n$} | {:<d$} | {:>w$} | {:>w$} | {:>c$} | {:<r$} | {:<t$} | {:<s$}",
+        "ID",
         "NAME",
         "DESCRIPTION",
         "WEIGHT",
+        "AGED",
+        "CHICKEN",
+        "READY",
         "TAGS",
+        "SCHEDULE",
+        i = W_ID,
         n = w_name,
         d = w_description,
         w = W_WEIGHT,
-        t = W_TAGS
+        c = W_CHICKEN,
+        r = W_READY,
+        t = W_TAGS,
+        s = W_SCHEDULE
     );
     println!("{}", "-".repeat(term_width));
-    for task in tasks {
+    for row in rows {
+        let task = row.task;
         // Tags need to be sorted to look consistent (HashSet is random!)
         let mut tags = task.get_tags();
         tags.sort();
@@ -107,58 +102,244 @@ pub fn print_task_table(tasks: &Vec<&Task>) {
             .collect::<Vec<_>>()
             .join(" ");
         let tag_lines = wrap(&tags_string, W_TAGS);
-        let name_lines = wrap(task.get_name(), w_name);
+        let indent = "  ".repeat(row.depth);
+        let name_lines: Vec<String> =
+            wrap(task.get_name(), w_name.saturating_sub(indent.len()).max(1))
+                .into_iter()
+                .map(|line| format!("{indent}{line}"))
+                .collect();
         let desc_lines = wrap(task.get_description(), w_description);
-        let max_lines = name_lines.len().max(desc_lines.len()).max(tag_lines.len());
+        let schedule = row.schedule();
+        let schedule_lines = wrap(&schedule, W_SCHEDULE);
+        let max_lines = name_lines
+            .len()
+            .max(desc_lines.len())
+            .max(tag_lines.len())
+            .max(schedule_lines.len());
         for i in 0..max_lines {
-            let name_part = name_lines.get(i).map(|s| s.as_ref()).unwrap_or("");
+            let name_part = name_lines.get(i).map(String::as_str).unwrap_or("");
             let desc_part = desc_lines.get(i).map(|s| s.as_ref()).unwrap_or("");
             let tags_part = tag_lines.get(i).map(|s| s.as_ref()).unwrap_or("");
+            let schedule_part = schedule_lines.get(i).map(|s| s.as_ref()).unwrap_or("");
 
             // Only print Weight/Tags on the FIRST line of the row
-            let weight_part = if i == 0 {
-                truncate(&task.get_weight().to_string(), W_WEIGHT)
+            let (id_part, weight_part, aged_part, chicken_part, ready_part) = if i == 0 {
+                (
+                    truncate(&task.get_id().to_string(), W_ID),
+                    truncate(&task.get_weight().to_string(), W_WEIGHT),
+                    truncate(&row.effective_weight.to_string(), W_WEIGHT),
+                    truncate(&task.get_rejections().to_string(), W_CHICKEN),
+                    if row.blocked { "blocked" } else { "ready" },
+                )
             } else {
-                "".to_owned()
+                (
+                    "".to_owned(),
+                    "".to_owned(),
+                    "".to_owned(),
+                    "".to_owned(),
+                    "",
+                )
             };
 
             println!(
-                "{:<n$} | {:<d$} | {:>w$} | {:<t$}",
+                "{:>i$} | {:<n$} | {:<d$} | {:>w$} | {:>w$} | {:>c$} | {:<r$} | {:<t$} | {:<s$}",
+                id_part,
                 name_part,
                 desc_part,
                 weight_part,
+                aged_part,
+                chicken_part,
+                ready_part,
                 tags_part,
+                schedule_part,
+                i = W_ID,
                 n = w_name,
                 d = w_description,
                 w = W_WEIGHT,
-                t = W_TAGS
+                c = W_CHICKEN,
+                r = W_READY,
+                t = W_TAGS,
+                s = W_SCHEDULE
             );
         }
         println!("{}", "-".repeat(term_width));
     }
 }
 
+pub fn print_done_table(completions: &[&Completion]) {
+    let term_width = if let Some((Width(w), _)) = terminal_size() {
+        w as usize
+    } else {
+        80
+    };
+
+    const W_DONE: usize = 16;
+    const W_WEIGHT: usize = 6;
+    const W_CHICKEN: usize = 7;
+    const W_TAGS: usize = 20;
+    const BORDER_OVERHEAD: usize = 12;
+
+    let w_name = term_width
+        .saturating_sub(W_DONE + W_WEIGHT + W_CHICKEN + W_TAGS + BORDER_OVERHEAD)
+        .max(5);
+    println!(
+        "{:<o$} | {:<n$} | {:>w$} | {:>c$} | {:<t$}",
+        "DONE",
+        "NAME",
+        "WEIGHT",
+        "CHICKEN",
+        "TAGS",
+        o = W_DONE,
+        n = w_name,
+        w = W_WEIGHT,
+        c = W_CHICKEN,
+        t = W_TAGS
+    );
+    println!("{}", "-".repeat(term_width));
+    for completion in completions {
+        let task = &completion.task;
+        let mut tags = task.get_tags();
+        tags.sort();
+        let tags_string = tags.join(" ");
+        let tag_lines = wrap(&tags_string, W_TAGS);
+        let name_lines = wrap(task.get_name(), w_name);
+        let max_lines = name_lines.len().max(tag_lines.len());
+        for i in 0..max_lines {
+            let name_part = name_lines.get(i).map(|s| s.as_ref()).unwrap_or("");
+            let tags_part = tag_lines.get(i).map(|s| s.as_ref()).unwrap_or("");
+            let (done_part, weight_part, chicken_part) = if i == 0 {
+                (
+                    format_timestamp(completion.completed),
+                    truncate(&task.get_weight().to_string(), W_WEIGHT),
+                    truncate(&task.get_rejections().to_string(), W_CHICKEN),
+                )
+            } else {
+                ("".to_owned(), "".to_owned(), "".to_owned())
+            };
+            println!(
+                "{:<o$} | {:<n$} | {:>w$} | {:>c$} | {:<t$}",
+                done_part,
+                name_part,
+                weight_part,
+                chicken_part,
+                tags_part,
+                o = W_DONE,
+                n = w_name,
+                w = W_WEIGHT,
+                c = W_CHICKEN,
+                t = W_TAGS
+            );
+        }
+    }
+}
+
+pub fn print_stats(stats: &Stats) {
+    const W_LABEL: usize = 20;
+    const W_COUNT: usize = 7;
+
+    println!("{:<l$} | {:>c$}", "DAY", "DONE", l = W_LABEL, c = W_COUNT);
+    for day in &stats.per_day {
+        println!(
+            "{:<l$} | {:>c$}",
+            day.day,
+            day.completed,
+            l = W_LABEL,
+            c = W_COUNT
+        );
+    }
+    println!();
+    println!(
+        "{:<l$} | {:>c$}",
+        "WEEK OF",
+        "DONE",
+        l = W_LABEL,
+        c = W_COUNT
+    );
+    for week in &stats.per_week {
+        println!(
+            "{:<l$} | {:>c$}",
+            week.day,
+            week.completed,
+            l = W_LABEL,
+            c = W_COUNT
+        );
+    }
+    println!();
+    let ratio = match stats.penguin_ratio() {
+        Some(ratio) => format!("{:.0}% penguin", ratio * 100.0),
+        None => "no pops yet".to_owned(),
+    };
+    println!(
+        "Penguins: {}, chickens: {} ({ratio})",
+        stats.penguins, stats.chickens
+    );
+    println!(
+        "Daily streak: {} days, longest {} days",
+        stats.current_streak, stats.longest_streak
+    );
+    if !stats.most_avoided.is_empty() {
+        println!();
+        println!(
+            "{:<l$} | {:>c$} | STATUS",
+            "MOST AVOIDED",
+            "CHICKEN",
+            l = W_LABEL,
+            c = W_COUNT
+        );
+        for task in &stats.most_avoided {
+            let status = if task.completed { "done" } else { "open" };
+            println!(
+                "{:<l$} | {:>c$} | {status}",
+                truncate(&task.name, W_LABEL),
+                task.rejections,
+                l = W_LABEL,
+                c = W_COUNT
+            );
+        }
+    }
+    if !stats.per_tag.is_empty() {
+        println!();
+        println!(
+            "{:<l$} | {:>c$} | {:>c$}",
+            "TAG",
+            "DONE",
+            "7 DAYS",
+            l = W_LABEL,
+            c = W_COUNT
+        );
+        for tag in &stats.per_tag {
+            println!(
+                "{:<l$} | {:>c$} | {:>c$}",
+                truncate(&tag.tag, W_LABEL),
+                tag.completed,
+                tag.last_week,
+                l = W_LABEL,
+                c = W_COUNT
+            );
+        }
+    }
+}
+
+// Counts characters, not bytes, so names outside ASCII are cut on a character boundary.
 fn truncate(s: &str, max_width: usize) -> String {
-    if s.len() > max_width {
-        format!("{}..", &s[..max_width - 2])
+    if s.chars().count() > max_width {
+        format!("{}..", s.chars().take(max_width - 2).collect::<String>())
     } else {
         s.to_string()
     }
 }
-pub fn print_single_task(task: &Task) {
-    print_task_table(&vec![task; 1]);
+pub fn print_single_task(row: TaskRow) {
+    print_task_table(&[row]);
 }
-pub fn get_yes_no() -> Result<String, HeapError> {
-    print!("[y/n]: ");
-    stdout().flush().unwrap(); //Flush so prompt appears before user input.
 
-    let mut input = String::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    match stdin().read_line(&mut input) {
-        Ok(_) => {
-            input = input.trim().to_owned();
-            Ok(input)
-        }
-        Err(e) => Err(HeapError::FileError(e)),
+    #[test]
+    fn truncate_cuts_multibyte_names_on_character_boundaries() {
+        assert_eq!(truncate("Café au lait", 20), "Café au lait");
+        assert_eq!(truncate("Écrire les tâches", 8), "Écrire..");
+        assert_eq!(truncate("日本語のタスク", 5), "日本語..");
     }
 }
@@ -0,0 +1,367 @@
+use crate::{HeapError, Task, Tasks, csv, storage::Storage};
+use chrono::{Local, TimeZone, Utc};
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+// Summaries of pops start with this, followed by the task's quoted name.
+pub const POP_SUMMARY: &str = "pop ";
+
+// One task before and after a mutation. A push has no `before`, a removal no `after`.
+#[derive(Clone)]
+pub struct Change {
+    before: Option<Task>,
+    after: Option<Task>,
+}
+impl Change {
+    pub fn new(before: Option<Task>, after: Option<Task>) -> Self {
+        Self { before, after }
+    }
+    pub fn before(&self) -> Option<&Task> {
+        self.before.as_ref()
+    }
+    pub fn after(&self) -> Option<&Task> {
+        self.after.as_ref()
+    }
+    fn inverted(&self) -> Self {
+        Self {
+            before: self.after.clone(),
+            after: self.before.clone(),
+        }
+    }
+    // Lenient on purpose: a task that is already gone stays gone, and a task that
+    // already exists is overwritten, so replaying never fails halfway through.
+    pub fn apply(&self, heap: &mut Tasks, storage: &mut dyn Storage) -> Result<(), HeapError> {
+        if let Some(before) = &self.before {
+            let hash = before.get_hash();
+            if heap.remove(&hash).is_some() {
+                storage.remove(&hash)?;
+            }
+        }
+        if let Some(after) = &self.after {
+            let hash = after.get_hash();
+            if heap.insert(hash, after.clone()).is_some() {
+                storage.update(&hash, after)?;
+            } else {
+                storage.insert(after)?;
+            }
+        }
+        Ok(())
+    }
+    fn to_fields(&self) -> [String; 2] {
+        let encode = |task: &Option<Task>| task.as_ref().map(Task::to_string).unwrap_or_default();
+        [encode(&self.before), encode(&self.after)]
+    }
+    fn from_fields(before: &str, after: &str) -> Result<Self, HeapError> {
+        let decode = |field: &str| match field {
+            "" => Ok(None),
+            record => record.parse().map(Some),
+        };
+        Ok(Self::new(decode(before)?, decode(after)?))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EntryKind {
+    Op,
+    Undo,
+    Redo,
+}
+impl EntryKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::Op => "op",
+            EntryKind::Undo => "undo",
+            EntryKind::Redo => "redo",
+        }
+    }
+}
+
+struct Entry {
+    id: u64,
+    timestamp: i64,
+    kind: EntryKind,
+    // Id of the operation an undo or redo applies to.
+    target: u64,
+    summary: String,
+    changes: Vec<Change>,
+}
+impl Entry {
+    fn to_record(&self) -> String {
+        let mut fields = vec![
+            self.id.to_string(),
+            self.timestamp.to_string(),
+            self.kind.as_str().to_owned(),
+            self.target.to_string(),
+            self.summary.clone(),
+        ];
+        fields.extend(self.changes.iter().flat_map(Change::to_fields));
+        csv::encode_record(fields)
+    }
+    fn from_record(record: &str) -> Result<Self, HeapError> {
+        let corrupt = || HeapError::CorruptData(record.to_owned());
+        let fields = csv::decode_record(record)?;
+        let [id, timestamp, kind, target, summary, changes @ ..] = fields.as_slice() else {
+            return Err(corrupt());
+        };
+        if changes.len() % 2 != 0 {
+            return Err(corrupt());
+        }
+        Ok(Self {
+            id: id.parse().map_err(|_| corrupt())?,
+            timestamp: timestamp.parse().map_err(|_| corrupt())?,
+            kind: match kind.as_str() {
+                "op" => EntryKind::Op,
+                "undo" => EntryKind::Undo,
+                "redo" => EntryKind::Redo,
+                _ => return Err(corrupt()),
+            },
+            target: target.parse().map_err(|_| corrupt())?,
+            summary: summary.to_owned(),
+            changes: changes
+                .chunks(2)
+                .map(|pair| Change::from_fields(&pair[0], &pair[1]))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+pub struct HistoryLine {
+    pub timestamp: String,
+    pub kind: EntryKind,
+    pub summary: String,
+    pub undone: bool,
+}
+
+// Append-only log of every mutation, kept next to the heap's database. Undo and redo
+// are logged as entries of their own, so the file is never rewritten. New entries
+// are buffered until `save`, which runs only once the heap itself was saved.
+pub struct Journal {
+    path: Option<PathBuf>,
+    entries: Vec<Entry>,
+    saved: usize,
+    // Operations that can be undone, oldest first, and those that can be redone.
+    done: Vec<u64>,
+    undone: Vec<u64>,
+}
+impl Journal {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, HeapError> {
+        let path = path.into();
+        let mut journal = Self::in_memory();
+        if path.exists() {
+            let data = fs::read_to_string(&path)?;
+            for record in csv::split_records(&data) {
+                journal.push(Entry::from_record(record)?);
+            }
+        }
+        journal.saved = journal.entries.len();
+        journal.path = Some(path);
+        Ok(journal)
+    }
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            entries: Vec::new(),
+            saved: 0,
+            done: Vec::new(),
+            undone: Vec::new(),
+        }
+    }
+    pub fn record(&mut self, summary: impl Into<String>, changes: Vec<Change>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.append(EntryKind::Op, 0, summary.into(), changes);
+    }
+    // Returns the summary and the changes to apply for each undone operation, most
+    // recent first.
+    pub fn undo(&mut self, count: usize) -> Result<Vec<(String, Vec<Change>)>, HeapError> {
+        if self.done.is_empty() {
+            return Err(HeapError::NothingToUndo);
+        }
+        let mut undone = Vec::new();
+        for _ in 0..count {
+            let Some(&target) = self.done.last() else {
+                break;
+            };
+            let entry = self.entry(target)?;
+            let summary = entry.summary.clone();
+            let changes: Vec<Change> = entry.changes.iter().rev().map(Change::inverted).collect();
+            self.append(EntryKind::Undo, target, summary.clone(), Vec::new());
+            undone.push((summary, changes));
+        }
+        Ok(undone)
+    }
+    pub fn redo(&mut self, count: usize) -> Result<Vec<(String, Vec<Change>)>, HeapError> {
+        if self.undone.is_empty() {
+            return Err(HeapError::NothingToRedo);
+        }
+        let mut redone = Vec::new();
+        for _ in 0..count {
+            let Some(&target) = self.undone.last() else {
+                break;
+            };
+            let entry = self.entry(target)?;
+            let summary = entry.summary.clone();
+            let changes = entry.changes.clone();
+            self.append(EntryKind::Redo, target, summary.clone(), Vec::new());
+            redone.push((summary, changes));
+        }
+        Ok(redone)
+    }
+    // The task removed by the most recent pop that was not undone.
+    pub fn last_popped(&self) -> Option<&Task> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.kind == EntryKind::Op && self.done.contains(&entry.id))
+            .find(|entry| entry.summary.starts_with(POP_SUMMARY))
+            .and_then(|entry| entry.changes.first())
+            .and_then(|change| change.before.as_ref())
+    }
+    // Every version of every task the journal holds, any of which an undo or redo can
+    // bring back.
+    pub fn tasks(&self) -> impl Iterator<Item = &Task> {
+        self.entries
+            .iter()
+            .flat_map(|entry| &entry.changes)
+            .flat_map(|change| change.before.iter().chain(&change.after))
+    }
+    // The most recent `count` entries, newest first.
+    pub fn history(&self, count: usize) -> Vec<HistoryLine> {
+        self.entries
+            .iter()
+            .rev()
+            .take(count)
+            .map(|entry| HistoryLine {
+                timestamp: format_timestamp(entry.timestamp),
+                kind: entry.kind,
+                summary: entry.summary.clone(),
+                undone: entry.kind == EntryKind::Op && self.undone.contains(&entry.id),
+            })
+            .collect()
+    }
+    pub fn save(&mut self) -> Result<(), HeapError> {
+        let Some(path) = &self.path else {
+            self.saved = self.entries.len();
+            return Ok(());
+        };
+        if self.saved == self.entries.len() {
+            return Ok(());
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let mut writer = BufWriter::new(file);
+        for entry in &self.entries[self.saved..] {
+            writeln!(writer, "{}", entry.to_record())?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        self.saved = self.entries.len();
+        Ok(())
+    }
+    fn append(&mut self, kind: EntryKind, target: u64, summary: String, changes: Vec<Change>) {
+        let id = self.entries.last().map(|entry| entry.id + 1).unwrap_or(1);
+        self.push(Entry {
+            id,
+            timestamp: Utc::now().timestamp(),
+            kind,
+            target,
+            summary,
+            changes,
+        });
+    }
+    fn push(&mut self, entry: Entry) {
+        match entry.kind {
+            EntryKind::Op => {
+                self.done.push(entry.id);
+                self.undone.clear();
+            }
+            EntryKind::Undo => {
+                self.done.retain(|id| *id != entry.target);
+                self.undone.push(entry.target);
+            }
+            EntryKind::Redo => {
+                self.undone.retain(|id| *id != entry.target);
+                self.done.push(entry.target);
+            }
+        }
+        self.entries.push(entry);
+    }
+    fn entry(&self, id: u64) -> Result<&Entry, HeapError> {
+        self.entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| HeapError::CorruptData(format!("journal entry {id} is missing")))
+    }
+}
+
+pub fn format_timestamp(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(journal: &mut Journal, name: &str) {
+        journal.record(
+            format!("push \"{name}\""),
+            vec![Change::new(None, Some(Task::from_arg(name)))],
+        );
+    }
+
+    #[test]
+    fn undo_and_redo_follow_the_stack() {
+        let mut journal = Journal::in_memory();
+        push(&mut journal, "a");
+        push(&mut journal, "b");
+
+        let undone = journal.undo(1).unwrap();
+        assert_eq!(undone.len(), 1);
+        assert_eq!(undone[0].0, "push \"b\"");
+        assert!(undone[0].1[0].after.is_none());
+
+        let redone = journal.redo(3).unwrap();
+        assert_eq!(redone.len(), 1);
+        assert!(redone[0].1[0].after.is_some());
+
+        assert_eq!(journal.undo(5).unwrap().len(), 2);
+        assert!(matches!(journal.undo(1), Err(HeapError::NothingToUndo)));
+
+        // A new operation discards whatever could have been redone.
+        push(&mut journal, "c");
+        assert!(matches!(journal.redo(1), Err(HeapError::NothingToRedo)));
+    }
+
+    #[test]
+    fn entries_round_trip_through_records() {
+        let entry = Entry {
+            id: 7,
+            timestamp: 1_700_000_000,
+            kind: EntryKind::Op,
+            target: 0,
+            summary: "edit \"a, b\"".to_owned(),
+            changes: vec![Change::new(
+                Some(Task::from_arg("a, b")),
+                Some(Task::from_arg("c")),
+            )],
+        };
+        let parsed = Entry::from_record(&entry.to_record()).unwrap();
+        assert_eq!(parsed.id, 7);
+        assert_eq!(parsed.summary, entry.summary);
+        assert_eq!(
+            parsed.changes[0].before.as_ref().unwrap().get_name(),
+            "a, b"
+        );
+        assert_eq!(parsed.changes[0].after.as_ref().unwrap().get_name(), "c");
+    }
+}
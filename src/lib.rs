@@ -0,0 +1,29 @@
+// The task heap as a library: `TaskHeap` holds one heap's tasks together with the
+// storage, journal, completion log and backups behind it, and never reads stdin or prints. Front ends
+// such as the `task-heap` binary ask their questions through a `prompt::Prompter` and
+// show what the storage reports through a `storage::Notify` callback.
+pub mod aging;
+pub mod backup;
+pub mod chicken;
+pub mod completion;
+pub mod config;
+mod csv;
+pub mod dates;
+pub mod error;
+mod heap;
+pub mod journal;
+pub mod prompt;
+pub mod recurrence;
+pub mod selection;
+pub mod stats;
+pub mod storage;
+pub mod task;
+
+pub use error::HeapError;
+pub use heap::{TaskHeap, Weighting};
+pub use task::Task;
+
+use std::collections::HashMap;
+
+pub type Hash = [u8; 32];
+pub type Tasks = HashMap<Hash, Task>;
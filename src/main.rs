@@ -1,28 +1,32 @@
 //TODO: store hash
 //TODO: Move to stack?
-//TODO: Multiple stacks, the stacks are in a heap
-mod error;
-use error::HeapError;
-mod task;
-use task::Task;
 mod io;
-use io::{print_single_task, print_task_table, read_task_heap, write_task_heap};
+use io::{TaskRow, print_done_table, print_single_task, print_stats, print_task_table};
 mod commands;
-use crate::{commands::Commands, io::get_yes_no};
+use crate::commands::Commands;
 use commands::Commands::*;
+use task_heap::{
+    HeapError, Task, TaskHeap, Weighting,
+    config::Config,
+    dates::{local_day, parse_until, start_of_day},
+    journal::{EntryKind, format_timestamp},
+    prompt::{AlwaysNo, AlwaysYes, Interactive, Prompter},
+    selection::{Selection, SelectionStrategy},
+    storage::HeapRegistry,
+};
 
-use rand::{distributions::WeightedIndex, prelude::*};
+use chrono::{NaiveDate, Utc};
+use rand::{SeedableRng, rngs::StdRng};
 use std::{
-    collections::HashMap,
+    collections::HashSet,
     env,
-    io::{Write, stdin, stdout},
+    io::{IsTerminal, stdin},
     iter::{Peekable, Skip},
+    path::PathBuf,
     vec::IntoIter,
 };
 
 type ArgsIter = Peekable<Skip<IntoIter<String>>>;
-type Hash = [u8; 32];
-type TaskHeap = HashMap<Hash, Task>;
 fn print_help() {
     const VERSION: &str = env!("CARGO_PKG_VERSION");
     println!("task-heap v{VERSION} prints tasks");
@@ -33,21 +37,60 @@ fn print_help() {
     println!("                          with optional tags and description.");
     println!();
     println!("\t-o, --pop               Pop a task at random from the task heap");
-    println!("                          by weight, with optional tag filter.");
+    println!("                          by weight, with optional tag filter. With N,");
+    println!("                          pop N different tasks.");
+    println!();
+    println!("\t-mo, --meta-pop         Pop a heap at random by heap weight, then a");
+    println!("                          task from it, with optional tag filter.");
+    println!();
+    println!("\t--peek                  Show each task's chance of being popped, with");
+    println!("                          optional tag filter, and the task a pop would");
+    println!("                          offer now. Nothing is removed.");
     println!();
-    println!("\t-d, --delete            Delete a task by name or by tag.");
+    println!("\t-d, --delete            Delete a task by name, ID or tag.");
     println!();
     println!("\t-r, --reset             Delete all tasks.");
     println!();
     println!("\t-e, --edit              Edit a task's name, description, tags or");
-    println!("                          weight.");
+    println!("                          weight. The task is given by name or ID.");
     println!();
     println!("\t-ct, --clear-tags       Clear all tags from a task by name.");
     println!();
     println!("\t-l, --list              List all tasks or tasks filtered by tag.");
+    println!("                          With --snoozed, list the snoozed tasks instead.");
+    println!("                          With --tree, list subtasks under their parent.");
+    println!();
+    println!("\t--snooze                Hide a task from pops and lists until a time:");
+    println!("                          <name> 3d, <name> +12h (m, h, d or w),");
+    println!("                          <name> YYYY-MM-DD[THH:MM], or <name> now to wake it.");
+    println!();
+    println!("\t--done                  List completed tasks, with optional tag filter");
+    println!("                          and --since/--until dates.");
+    println!();
+    println!("\t--stats                 Show completions per day and week, streaks,");
+    println!("                          the most avoided tasks and completions per tag.");
+    println!("                          With json, print them as JSON.");
     println!();
     println!("\t-h, --help              Print this message.");
     println!();
+    println!("\t-u, --undo              Undo the last operation, or the last N.");
+    println!();
+    println!("\t--redo                  Redo the last undone operation, or the last N.");
+    println!();
+    println!("\t--history               Show the last 10 operations, or the last N.");
+    println!();
+    println!("\t--restore               List the backups of the heap, or restore");
+    println!("                          backup N after confirmation.");
+    println!();
+    println!("\t--heaps                 List the named heaps, marking the one in use.");
+    println!();
+    println!("\t--new-heap              Create an empty named heap, with optional");
+    println!("                          weight.");
+    println!();
+    println!("\t--edit-heap             Rename a heap or change its weight.");
+    println!();
+    println!("\t--delete-heap           Delete a heap and all of its tasks.");
+    println!();
     println!("Options:");
     println!("\t-n, --name              Specify a new name when editing a task.");
     println!();
@@ -62,6 +105,38 @@ fn print_help() {
     println!();
     println!("\t-n, --name              Specify a new name when editing a task.");
     println!();
+    println!("\t--repeat                Make a task come back after it is popped:");
+    println!("                          daily, weekly, every:N (days) or");
+    println!("                          weekdays:mon,thu. none stops it recurring.");
+    println!();
+    println!("\t--after                 Make a task wait until another, by name or ID,");
+    println!("                          is popped or deleted. It cannot be popped until");
+    println!("                          then.");
+    println!();
+    println!("\t--not-after             Stop a task waiting on another, by name or ID,");
+    println!("                          when editing.");
+    println!();
+    println!("\t--parent                Make a task a subtask of another, by name or ID.");
+    println!("                          Popping the parent pops one of its subtasks,");
+    println!("                          and the parent is done once all of them are.");
+    println!();
+    println!("\t--no-parent             Make a subtask top-level again when editing.");
+    println!();
+    println!("\t--since, --until        Only list tasks completed on or after, or on or");
+    println!("                          before, a date given as YYYY-MM-DD.");
+    println!();
+    println!("\t--heap                  Select the heap to work on. Defaults to the");
+    println!("                          configured default_heap, or \"default\".");
+    println!();
+    println!("\t--seed                  Seed the random choices, so that the same");
+    println!("                          heap always pops the same tasks.");
+    println!();
+    println!("\t--strategy              How a pop chooses: linear (default), uniform,");
+    println!("                          highest, round-robin or softmax[:temperature].");
+    println!();
+    println!("\t--yes, --no             Answer every confirmation with yes or no.");
+    println!("                          Without a terminal, the answer is no.");
+    println!();
 }
 
 fn join_args(args_iterator: &mut ArgsIter) -> String {
@@ -79,48 +154,178 @@ fn join_args(args_iterator: &mut ArgsIter) -> String {
     param.join(" ")
 }
 
-fn extract_array_by_tag<'a, F, R>(map: &'a TaskHeap, tags: &Vec<String>, closure: F) -> Vec<R>
-where
-    F: FnMut((&'a Hash, &'a Task)) -> R,
-{
-    map.iter()
-        .filter(|tuple| tuple.1.has_tags(tags))
-        .map(closure)
-        .collect()
+// Optional count argument, as in `--undo 3`.
+fn parse_count(contents: &str, default: usize, command: &str) -> Result<usize, HeapError> {
+    if contents.is_empty() {
+        return Ok(default);
+    }
+    match contents.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(HeapError::InvalidArgument((
+            contents.to_owned(),
+            command.to_owned(),
+        ))),
+    }
+}
+
+// First and last second of the local day `contents` names, as in `--since 2026-10-01`.
+fn day_bounds(contents: &str, command: &str) -> Result<(i64, i64), HeapError> {
+    if contents.is_empty() {
+        return Err(HeapError::MissingArgument((
+            "date".to_owned(),
+            command.to_owned(),
+        )));
+    }
+    NaiveDate::parse_from_str(contents, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| Some((start_of_day(day)?, start_of_day(day.succ_opt()?)? - 1)))
+        .ok_or_else(|| HeapError::InvalidArgument((contents.to_owned(), command.to_owned())))
+}
+
+// What listing a task needs from the heap, taken before the listing borrows it.
+struct RowContext {
+    weighting: Weighting,
+    blocked: HashSet<String>,
+    now: i64,
+}
+impl RowContext {
+    fn of(task_heap: &TaskHeap) -> Self {
+        Self {
+            weighting: task_heap.weighting(),
+            blocked: task_heap.blocked(),
+            now: Utc::now().timestamp(),
+        }
+    }
+    fn row<'a>(&self, task: &'a Task) -> TaskRow<'a> {
+        TaskRow {
+            task,
+            effective_weight: self.weighting.effective_weight(task, self.now),
+            blocked: self.blocked.contains(task.get_name()),
+            depth: 0,
+            now: self.now,
+        }
+    }
+    fn rows<'a>(&self, tasks: &[&'a Task]) -> Vec<TaskRow<'a>> {
+        tasks.iter().map(|task| self.row(task)).collect()
+    }
+    // Like `rows`, with the subtasks of each task right under it. A task whose parent
+    // is not listed starts a tree of its own.
+    fn tree_rows<'a>(&self, tasks: &[&'a Task]) -> Vec<TaskRow<'a>> {
+        let listed: HashSet<&str> = tasks.iter().map(|task| task.get_name()).collect();
+        let mut pending: Vec<(&Task, usize)> = tasks
+            .iter()
+            .rev()
+            .filter(|task| {
+                !task
+                    .get_parent()
+                    .is_some_and(|parent| listed.contains(parent))
+            })
+            .map(|task| (*task, 0))
+            .collect();
+        let mut rows = Vec::new();
+        while let Some((task, depth)) = pending.pop() {
+            rows.push(TaskRow {
+                depth,
+                ..self.row(task)
+            });
+            pending.extend(
+                tasks
+                    .iter()
+                    .rev()
+                    .filter(|child| child.get_parent() == Some(task.get_name()))
+                    .map(|child| (*child, depth + 1)),
+            );
+        }
+        rows
+    }
+}
+
+// A popped recurring task stays on the heap until its next occurrence, and the
+// parents whose last subtask was popped are done with it.
+fn print_popped(task_heap: &TaskHeap, done: &[Task]) {
+    for (index, task) in done.iter().enumerate() {
+        let name = task.get_name();
+        if index > 0 {
+            println!("\"{name}\" is done too, with all of its subtasks.");
+        }
+        if let Some(task) = task_heap.get(name) {
+            println!(
+                "\"{name}\" comes back on {}.",
+                local_day(task.get_hidden_until())
+            );
+        }
+    }
 }
 
-fn run_commands(commands: Vec<Commands>) -> Result<(), HeapError> {
-    //-> Result<(), HeapError> {
-    let mut task_heap = read_task_heap().unwrap_or_else(|err| {
-        println!("Error reading the task heap: {err}.\nCreating a new heap...");
-        HashMap::new()
-    });
+// Commands take a task by name or by ID, see `TaskHeap::find`.
+fn task_name(task_heap: &TaskHeap, name_or_id: &str) -> Result<String, HeapError> {
+    match task_heap.find(name_or_id) {
+        Some(task) => Ok(task.get_name().to_owned()),
+        None => Err(HeapError::TaskNotFound(name_or_id.to_owned())),
+    }
+}
+
+// Qualifiers naming another task take it by name or by ID too. A prerequisite being
+// removed may be gone from the heap already, so its name is kept when nothing matches.
+fn resolve_task_names(task_heap: &TaskHeap, qualifier: Commands) -> Result<Commands, HeapError> {
+    Ok(match qualifier {
+        After(name_or_id) => After(task_name(task_heap, &name_or_id)?),
+        NotAfter(name_or_id) => NotAfter(task_name(task_heap, &name_or_id).unwrap_or(name_or_id)),
+        Parent(name_or_id) => Parent(task_name(task_heap, &name_or_id)?),
+        qualifier => qualifier,
+    })
+}
+
+fn print_backup(backup: Option<PathBuf>) {
+    if let Some(path) = backup {
+        println!("Backup saved to {}", path.display());
+    }
+}
+
+fn run_commands(
+    commands: Vec<Commands>,
+    task_heap: &mut TaskHeap,
+    heaps: &HeapRegistry,
+    heap_name: &str,
+    prompter: &mut dyn Prompter,
+    rng: &mut StdRng,
+    strategy: &dyn SelectionStrategy,
+) -> Result<(), HeapError> {
+    if task_heap.is_new() {
+        println!("No task heap found.\nCreating a new heap...");
+    }
     let mut command_iter = commands.into_iter().peekable();
     while let Some(command) = command_iter.next() {
         match command {
             Push(ref argument) => {
-                if task_heap.contains_key(&Task::hash_fn(argument)) {
-                    return Err(HeapError::TaskAlreadyExists(argument.to_owned()));
-                };
                 let mut new_task = Task::from_arg(argument);
                 while let Some(qualifier) = command_iter.next_if(|cmd| cmd.is_valid_for(&command)) {
-                    match qualifier {
+                    match resolve_task_names(task_heap, qualifier)? {
                         Description(desc) => {
                             new_task.set_desc(desc);
                         }
                         Weight(weight_str) => {
-                            new_task.set_weight(weight_str);
+                            new_task.set_weight(weight_str)?;
                         }
                         Tag(tags) => {
                             new_task.add_tags(tags);
                         }
+                        Repeat(recurrence) => {
+                            new_task.set_recurrence(recurrence);
+                        }
+                        After(name) => {
+                            new_task.add_prerequisite(name);
+                        }
+                        Parent(name) => {
+                            new_task.set_parent(Some(name));
+                        }
                         //Cannot be a non-qualifier
                         _ => unreachable!(),
                     };
                 }
-                task_heap.insert(new_task.get_hash(), new_task);
+                task_heap.push(new_task)?;
             }
-            Pop => {
+            Pop(1) | MetaPop(1) => {
                 let tags = command_iter
                     .next_if(|cmd| matches!(cmd, Tag(_)))
                     .map(|cmd| match cmd {
@@ -128,35 +333,87 @@ fn run_commands(commands: Vec<Commands>) -> Result<(), HeapError> {
                         _ => unreachable!(),
                     })
                     .unwrap_or_default();
-                let tasks = extract_array_by_tag(&task_heap, &tags, |tuple| tuple.1);
-                if tasks.is_empty() {
-                    if !tags.is_empty() {
-                        return Err(HeapError::NoTaggedElements(tags.join(",")));
-                    } else {
-                        return Err(HeapError::NoTasksOnHeap);
-                    }
-                }
-                let weights: Vec<u32> = tasks.iter().map(|task| task.get_weight()).collect();
-                let hashes: Vec<Hash> = tasks.into_iter().map(|task| task.get_hash()).collect();
-                let distribution = WeightedIndex::new(&weights)
-                    .expect("The set of tasks to choose from should not be empty");
-                let mut rng = thread_rng();
-
-                let selected_hash = hashes[distribution.sample(&mut rng)];
-                let selected_task = &task_heap
-                    .get(&selected_hash)
-                    .expect("Error with random number generation or elements selection");
+                let context = RowContext::of(task_heap);
+                let selected_task = task_heap.pick(&tags, strategy, rng)?;
                 println!("The selected task for completion is:");
-                print_single_task(selected_task);
-                print!("Are you certain you can complete it? Are you a chicken or a penguin?");
-                let input = get_yes_no()?;
-                if input.to_lowercase() == "y" {
-                    task_heap.remove(&selected_hash);
+                print_single_task(context.row(selected_task));
+                let name = selected_task.get_name().to_owned();
+                if prompter.confirm(
+                    "Are you certain you can complete it? Are you a chicken or a penguin?",
+                )? {
+                    let done = task_heap.pop(&name)?;
                     println!("Task was popped. Penguin wishes you good luck!");
+                    print_popped(task_heap, &done);
                 } else {
+                    task_heap.reject(&name)?;
                     println!("You gave up on the task. *Chicken noises*");
                 }
             }
+            Pop(count) | MetaPop(count) => {
+                let tags = command_iter
+                    .next_if(|cmd| matches!(cmd, Tag(_)))
+                    .map(|cmd| match cmd {
+                        Tag(names) => names,
+                        _ => unreachable!(),
+                    })
+                    .unwrap_or_default();
+                let context = RowContext::of(task_heap);
+                let selected_tasks = task_heap.pick_many(&tags, count, strategy, rng)?;
+                if selected_tasks.len() < count {
+                    println!("Only {} tasks can be popped.", selected_tasks.len());
+                }
+                println!("The selected tasks for completion are:");
+                print_task_table(&context.rows(&selected_tasks));
+                let names: Vec<String> = selected_tasks
+                    .iter()
+                    .map(|task| task.get_name().to_owned())
+                    .collect();
+                // One answer for the whole batch, or else one per task.
+                if prompter.confirm(
+                    "Are you certain you can complete all of them? Are you a chicken or a penguin?",
+                )? {
+                    let mut done = Vec::new();
+                    for name in &names {
+                        done.push(task_heap.pop(name)?);
+                    }
+                    println!("Tasks were popped. Penguin wishes you good luck!");
+                    for done in &done {
+                        print_popped(task_heap, done);
+                    }
+                    continue;
+                }
+                for name in &names {
+                    if prompter.confirm(&format!("Can you complete \"{name}\" then?"))? {
+                        let done = task_heap.pop(name)?;
+                        println!("Task was popped.");
+                        print_popped(task_heap, &done);
+                    } else {
+                        task_heap.reject(name)?;
+                        println!("*Chicken noises*");
+                    }
+                }
+            }
+            Peek => {
+                let tags = command_iter
+                    .next_if(|cmd| matches!(cmd, Tag(_)))
+                    .map(|cmd| match cmd {
+                        Tag(names) => names,
+                        _ => unreachable!(),
+                    })
+                    .unwrap_or_default();
+                println!("{:>7} | {:>6} | TASK", "CHANCE", "WEIGHT");
+                for (candidate, probability) in task_heap.odds(&tags, strategy)? {
+                    println!(
+                        "{:>6.2}% | {:>6} | {}",
+                        probability * 100.0,
+                        candidate.weight,
+                        candidate.task.get_name()
+                    );
+                }
+                // Drawn like a pop, so with the same --seed a pop offers the same task.
+                let sampled = task_heap.pick(&tags, strategy, rng)?;
+                println!("A pop now would offer \"{}\".", sampled.get_name());
+            }
             Delete(argument) => {
                 let tags = command_iter
                     .next_if(|cmd| matches!(cmd, Tag(_)))
@@ -165,15 +422,9 @@ fn run_commands(commands: Vec<Commands>) -> Result<(), HeapError> {
                         _ => unreachable!(),
                     })
                     .unwrap_or_default();
+                let context = RowContext::of(task_heap);
                 let tasks = match tags {
-                    tags if !tags.is_empty() => {
-                        let task_vec = extract_array_by_tag(&task_heap, &tags, |tuple| tuple.1);
-                        if task_vec.is_empty() {
-                            return Err(HeapError::NoTaggedElements(tags.join(",")));
-                        } else {
-                            task_vec
-                        }
-                    }
+                    ref tags if !tags.is_empty() => task_heap.list(tags)?,
                     _ => {
                         if argument.is_empty() {
                             return Err(HeapError::MissingArgument((
@@ -181,107 +432,289 @@ fn run_commands(commands: Vec<Commands>) -> Result<(), HeapError> {
                                 "delete".to_owned(),
                             )));
                         }
-                        let hash = Task::hash_fn(&argument);
-                        let Some(task_ref) = task_heap.get(&hash) else {
+                        let Some(task_ref) = task_heap.find(&argument) else {
                             return Err(HeapError::TaskNotFound(argument));
                         };
                         vec![task_ref; 1]
                     }
                 };
                 println!("To be deleted:");
-                print_task_table(&tasks);
-                print!("Are you sure you want to delete?");
-                let answer = get_yes_no()?;
-                if answer.to_lowercase() == "y" {
-                    let hashes_to_remove: Vec<Hash> =
-                        tasks.into_iter().map(|task| task.get_hash()).collect();
-                    for hash in hashes_to_remove {
-                        task_heap.remove(&hash);
+                print_task_table(&context.rows(&tasks));
+                let name = tasks[0].get_name().to_owned();
+                if prompter.confirm("Are you sure you want to delete?")? {
+                    if tags.is_empty() {
+                        task_heap.delete(&name)?;
+                    } else {
+                        let (_, backup) = task_heap.delete_tagged(&tags)?;
+                        print_backup(backup);
                     }
                     println!("Tasks deleted. *Chicken noises*?");
                 }
             }
             Edit(ref argument) => {
-                let Some(task) = task_heap.get_mut(&Task::hash_fn(argument)) else {
-                    return Err(HeapError::TaskNotFound(argument.to_owned()));
-                };
-                let mut rehash = false;
+                let mut qualifiers = Vec::new();
+                while let Some(qualifier) = command_iter.next_if(|cmd| cmd.is_valid_for(&command)) {
+                    qualifiers.push(resolve_task_names(task_heap, qualifier)?);
+                }
+                let name = task_name(task_heap, argument)?;
+                task_heap.edit(&name, |task| {
+                    for qualifier in qualifiers {
+                        match qualifier {
+                            Name(name) => {
+                                task.set_name(name);
+                            }
+                            Description(desc) => {
+                                task.set_desc(desc);
+                            }
+                            Weight(weight_str) => {
+                                task.set_weight(weight_str)?;
+                            }
+                            Tag(tags) => {
+                                task.add_tags(tags);
+                            }
+                            Untag(tags) => {
+                                task.remove_tags(tags);
+                            }
+                            Repeat(recurrence) => {
+                                task.set_recurrence(recurrence);
+                            }
+                            After(name) => {
+                                task.add_prerequisite(name);
+                            }
+                            NotAfter(name) => {
+                                task.remove_prerequisite(&name);
+                            }
+                            Parent(name) => {
+                                task.set_parent(Some(name));
+                            }
+                            NoParent => {
+                                task.set_parent(None);
+                            }
+                            //Cannot be a non-qualifier
+                            _ => unreachable!(),
+                        };
+                    }
+                    Ok(())
+                })?;
+            }
+            ClearTags(argument) => {
+                task_heap.clear_tags(&task_name(task_heap, &argument)?)?;
+            }
+            List => {
+                let (mut tags, mut snoozed, mut tree) = (Vec::new(), false, false);
                 while let Some(qualifier) = command_iter.next_if(|cmd| cmd.is_valid_for(&command)) {
                     match qualifier {
-                        Name(name) => {
-                            task.set_name(name);
-                            rehash = true;
-                        }
-                        Description(desc) => {
-                            task.set_desc(desc);
-                        }
-                        Weight(weight_str) => {
-                            task.set_weight(weight_str);
-                        }
-                        Tag(tags) => {
-                            task.add_tags(tags);
-                        }
-                        Untag(tags) => {
-                            task.remove_tags(tags);
-                        }
+                        Tag(names) => tags.extend(names),
+                        Snoozed => snoozed = true,
+                        Tree => tree = true,
+                        // Weights are accepted but do not filter yet.
+                        Weight(_) => {}
                         //Cannot be a non-qualifier
                         _ => unreachable!(),
                     };
                 }
-                if rehash {
-                    let Some(updated_task) = task_heap.remove(&Task::hash_fn(argument)) else {
-                        unreachable!();
-                    };
-                    task_heap.insert(updated_task.get_hash(), updated_task);
+                let context = RowContext::of(task_heap);
+                let now = context.now;
+                // Snoozed tasks, and recurring ones not due yet, only show with --snoozed.
+                let tasks: Vec<&Task> = task_heap
+                    .list(&tags)?
+                    .into_iter()
+                    .filter(|task| task.is_hidden(now) == snoozed)
+                    .collect();
+                match (tasks.is_empty(), snoozed) {
+                    (true, false) => {
+                        println!("Every matching task is snoozed. See --list --snoozed.")
+                    }
+                    (true, true) => println!("No matching task is snoozed."),
+                    (false, _) if tree => print_task_table(&context.tree_rows(&tasks)),
+                    (false, _) => print_task_table(&context.rows(&tasks)),
                 }
             }
-            ClearTags(argument) => {
-                let Some(task) = task_heap.get_mut(&Task::hash_fn(&argument)) else {
-                    return Err(HeapError::TaskNotFound(argument.to_owned()));
-                };
-                task.clear_tags();
+            Snooze(argument, until) => {
+                let name = task_name(task_heap, &argument)?;
+                task_heap.snooze(&name, until)?;
+                if until > Utc::now().timestamp() {
+                    println!("\"{name}\" is snoozed until {}.", format_timestamp(until));
+                } else {
+                    println!("\"{name}\" is awake.");
+                }
             }
-            List => {
-                let tags = command_iter
-                    .next_if(|cmd| matches!(cmd, Tag(_)))
-                    .map(|cmd| match cmd {
-                        Tag(names) => names,
+            Done => {
+                let (mut tags, mut since, mut until) = (Vec::new(), None, None);
+                while let Some(qualifier) = command_iter.next_if(|cmd| cmd.is_valid_for(&command)) {
+                    match qualifier {
+                        Tag(names) => tags.extend(names),
+                        Since(timestamp) => since = Some(timestamp),
+                        Until(timestamp) => until = Some(timestamp),
+                        //Cannot be a non-qualifier
                         _ => unreachable!(),
-                    })
-                    .unwrap_or_default();
-                let tasks = extract_array_by_tag(&task_heap, &tags, |tuple| tuple.1);
-                if tasks.is_empty() {
-                    if tags.is_empty() {
-                        return Err(HeapError::NoTaggedElements(tags.join(",")));
-                    } else {
-                        return Err(HeapError::NoTasksOnHeap);
-                    }
+                    };
+                }
+                let completions = task_heap.completions().query(&tags, since, until);
+                if completions.is_empty() {
+                    println!("No completed tasks found.");
+                } else {
+                    print_done_table(&completions);
+                }
+            }
+            Stats(json) => {
+                let stats = task_heap.stats();
+                if json {
+                    let json = serde_json::to_string_pretty(&stats)
+                        .map_err(|e| HeapError::CorruptData(e.to_string()))?;
+                    println!("{json}");
+                } else {
+                    print_stats(&stats);
                 }
-                print_task_table(&tasks);
             }
             Reset => {
-                print!("Are you sure you want to erase your task heap?");
-                let answer = get_yes_no()?;
-                if answer.to_lowercase() == "y" {
-                    task_heap.clear();
+                if prompter.confirm("Are you sure you want to erase your task heap?")? {
+                    print_backup(task_heap.reset()?);
                 }
             }
             Help => {
                 print_help();
             }
+            Undo(count) => {
+                for summary in task_heap.undo(count)? {
+                    println!("Undid {summary}.");
+                }
+            }
+            Redo(count) => {
+                for summary in task_heap.redo(count)? {
+                    println!("Redid {summary}.");
+                }
+            }
+            History(count) => {
+                println!("{:<16} | {:<6} | ACTION", "TIME", "KIND");
+                for line in task_heap.history(count) {
+                    let kind = match line.kind {
+                        EntryKind::Op if line.undone => "undone",
+                        EntryKind::Op => "",
+                        EntryKind::Undo => "undo",
+                        EntryKind::Redo => "redo",
+                    };
+                    println!("{:<16} | {:<6} | {}", line.timestamp, kind, line.summary);
+                }
+            }
+            Restore(None) => {
+                let backups = task_heap.backups();
+                let snapshots = backups.list()?;
+                if snapshots.is_empty() {
+                    println!("There are no backups of this heap.");
+                }
+                for (number, snapshot) in snapshots.iter().enumerate() {
+                    println!(
+                        "{:>3}. {} before {} ({} tasks)",
+                        number + 1,
+                        snapshot.timestamp,
+                        snapshot.reason,
+                        backups.load(snapshot)?.len()
+                    );
+                }
+            }
+            Restore(Some(number)) => {
+                let mut snapshots = task_heap.backups().list()?;
+                if number > snapshots.len() {
+                    return Err(HeapError::BackupNotFound(number));
+                }
+                let snapshot = snapshots.swap_remove(number - 1);
+                println!(
+                    "Backup from {} holds {} tasks; the heap has {} now.",
+                    snapshot.timestamp,
+                    task_heap.backups().load(&snapshot)?.len(),
+                    task_heap.len()
+                );
+                if prompter.confirm("Are you sure you want to replace your task heap with it?")? {
+                    print_backup(task_heap.restore(&snapshot)?);
+                    println!("Backup restored.");
+                }
+            }
+            ListHeaps => {
+                println!("  {:<20} | {:>6}", "HEAP", "WEIGHT");
+                for name in heaps.list()? {
+                    let marker = if name == heap_name { "*" } else { " " };
+                    println!("{marker} {name:<20} | {:>6}", heaps.weight(&name)?);
+                }
+            }
+            NewHeap(ref name) => {
+                // The heap in use is written at the end of the run, so it counts as existing.
+                if name == heap_name || heaps.exists(name)? {
+                    return Err(HeapError::HeapAlreadyExists(name.to_owned()));
+                }
+                heaps.create(name)?;
+                println!("Created heap \"{name}\".");
+                while let Some(qualifier) = command_iter.next_if(|cmd| cmd.is_valid_for(&command)) {
+                    match qualifier {
+                        Weight(weight_str) => {
+                            heaps.set_weight(name, Task::parse_weight(weight_str)?)?;
+                        }
+                        //Cannot be a non-qualifier
+                        _ => unreachable!(),
+                    };
+                }
+            }
+            EditHeap(ref name) => {
+                let mut current_name = name.to_owned();
+                while let Some(qualifier) = command_iter.next_if(|cmd| cmd.is_valid_for(&command)) {
+                    match qualifier {
+                        Name(new_name) => {
+                            if current_name == heap_name {
+                                return Err(HeapError::HeapInUse(current_name));
+                            }
+                            heaps.rename(&current_name, &new_name)?;
+                            println!("Renamed heap \"{current_name}\" to \"{new_name}\".");
+                            current_name = new_name;
+                        }
+                        Weight(weight_str) => {
+                            heaps.set_weight(&current_name, Task::parse_weight(weight_str)?)?;
+                        }
+                        //Cannot be a non-qualifier
+                        _ => unreachable!(),
+                    };
+                }
+            }
+            DeleteHeap(name) => {
+                if name == heap_name {
+                    return Err(HeapError::HeapInUse(name));
+                }
+                if !heaps.exists(&name)? {
+                    return Err(HeapError::HeapNotFound(name));
+                }
+                if prompter.confirm(&format!(
+                    "Are you sure you want to delete the heap \"{name}\" and all of its tasks?"
+                ))? {
+                    heaps.delete(&name)?;
+                    println!("Heap deleted.");
+                }
+            }
 
-            Name(argument) | Description(argument) | Weight(argument) => {
+            Name(argument)
+            | Description(argument)
+            | Weight(argument)
+            | After(argument)
+            | NotAfter(argument)
+            | Parent(argument) => {
                 println!("Standalone task qualifiers are ignored: {argument}")
             }
+            Snoozed | Tree => {
+                println!("Standalone list qualifiers are ignored; use them with --list.")
+            }
+            NoParent => {
+                println!("Standalone --no-parent is ignored; use it with --edit.")
+            }
+            Since(_) | Until(_) => {
+                println!("Standalone date qualifiers are ignored.")
+            }
             Tag(argument) | Untag(argument) => {
                 println!("Standalone task qualifiers are ignored: {argument:?}")
             }
+            Repeat(_) => {
+                println!("Standalone task qualifiers are ignored: repeat")
+            }
         }
     }
-    match write_task_heap(task_heap) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(HeapError::FileError(e)),
-    }
+    task_heap.save()
 }
 
 fn main() -> Result<(), HeapError> {
@@ -289,10 +722,96 @@ fn main() -> Result<(), HeapError> {
     let mut args_iterator = args.into_iter().skip(1).peekable();
 
     let mut commands: Vec<Commands> = Vec::new();
+    let mut selected_heap: Option<String> = None;
+    let mut answer: Option<bool> = None;
+    let mut seed: Option<u64> = None;
+    let mut strategy: Option<Selection> = None;
 
     while let Some(arg) = args_iterator.next() {
         let contents: String = join_args(&mut args_iterator);
         commands.push(match arg.as_str() {
+            "--heap" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "heap name".to_owned(),
+                        "heap".to_owned(),
+                    )));
+                }
+                selected_heap = Some(contents);
+                continue;
+            }
+            "--seed" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "number".to_owned(),
+                        "seed".to_owned(),
+                    )));
+                }
+                seed = Some(contents.parse().map_err(|_| {
+                    HeapError::InvalidArgument((contents.to_owned(), "seed".to_owned()))
+                })?);
+                continue;
+            }
+            "--strategy" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "strategy name".to_owned(),
+                        "strategy".to_owned(),
+                    )));
+                }
+                strategy = Some(contents.parse().map_err(|_| {
+                    HeapError::InvalidArgument((contents.to_owned(), "strategy".to_owned()))
+                })?);
+                continue;
+            }
+            "--yes" | "--no" => {
+                let flag = &arg[2..];
+                if !contents.is_empty() {
+                    return Err(HeapError::DoesNotTakeArg(flag.to_owned()));
+                }
+                let yes = flag == "yes";
+                if answer.is_some_and(|other| other != yes) {
+                    return Err(HeapError::ConflictingArgs((
+                        "yes".to_owned(),
+                        "no".to_owned(),
+                    )));
+                }
+                answer = Some(yes);
+                continue;
+            }
+            "--heaps" => {
+                if !contents.is_empty() {
+                    return Err(HeapError::DoesNotTakeArg("heaps".to_owned()));
+                }
+                ListHeaps
+            }
+            "--new-heap" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "heap name".to_owned(),
+                        "new-heap".to_owned(),
+                    )));
+                }
+                NewHeap(contents)
+            }
+            "--edit-heap" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "heap name".to_owned(),
+                        "edit-heap".to_owned(),
+                    )));
+                }
+                EditHeap(contents)
+            }
+            "--delete-heap" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "heap name".to_owned(),
+                        "delete-heap".to_owned(),
+                    )));
+                }
+                DeleteHeap(contents)
+            }
             "-i" | "--push" => {
                 if contents.is_empty() {
                     return Err(HeapError::MissingArgument((
@@ -342,6 +861,49 @@ fn main() -> Result<(), HeapError> {
                 }
                 Untag(tags)
             }
+            "--after" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "task name".to_owned(),
+                        "after".to_owned(),
+                    )));
+                }
+                After(contents)
+            }
+            "--not-after" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "task name".to_owned(),
+                        "not-after".to_owned(),
+                    )));
+                }
+                NotAfter(contents)
+            }
+            "--parent" => {
+                if contents.is_empty() {
+                    return Err(HeapError::MissingArgument((
+                        "task name".to_owned(),
+                        "parent".to_owned(),
+                    )));
+                }
+                Parent(contents)
+            }
+            "--no-parent" => {
+                if !contents.is_empty() {
+                    return Err(HeapError::DoesNotTakeArg("no-parent".to_owned()));
+                }
+                NoParent
+            }
+            "--repeat" => match contents.as_str() {
+                "" => {
+                    return Err(HeapError::MissingArgument((
+                        "rule".to_owned(),
+                        "repeat".to_owned(),
+                    )));
+                }
+                "none" => Repeat(None),
+                rule => Repeat(Some(rule.parse()?)),
+            },
             "-w" | "--weight" => {
                 if contents.is_empty() {
                     return Err(HeapError::MissingArgument((
@@ -351,13 +913,22 @@ fn main() -> Result<(), HeapError> {
                 }
                 Weight(contents)
             }
-            "-o" | "--pop" => {
+            "-o" | "--pop" => Pop(parse_count(&contents, 1, "pop")?),
+            "-mo" | "--meta-pop" => MetaPop(parse_count(&contents, 1, "meta-pop")?),
+            "--peek" => {
                 if !contents.is_empty() {
-                    return Err(HeapError::DoesNotTakeArg("pop".to_owned()));
+                    return Err(HeapError::DoesNotTakeArg("peek".to_owned()));
                 }
-                Pop
+                Peek
             }
             "-d" | "--delete" => Delete(contents),
+            "-u" | "--undo" => Undo(parse_count(&contents, 1, "undo")?),
+            "--redo" => Redo(parse_count(&contents, 1, "redo")?),
+            "--history" => History(parse_count(&contents, 10, "history")?),
+            "--restore" => Restore(match contents.is_empty() {
+                true => None,
+                false => Some(parse_count(&contents, 1, "restore")?),
+            }),
             // Needs to know if there are tags to consider the arg is incomplete
             "-r" | "--reset" => {
                 if !contents.is_empty() {
@@ -383,12 +954,55 @@ fn main() -> Result<(), HeapError> {
                 }
                 ClearTags(contents)
             }
+            "--snooze" => {
+                // The last word is the time, the rest the task's name.
+                let Some((name, when)) = contents.rsplit_once(' ') else {
+                    return Err(HeapError::MissingArgument((
+                        "name and a duration or date".to_owned(),
+                        "snooze".to_owned(),
+                    )));
+                };
+                let until = parse_until(when, Utc::now().timestamp()).ok_or_else(|| {
+                    HeapError::InvalidArgument((when.to_owned(), "snooze".to_owned()))
+                })?;
+                Snooze(name.to_owned(), until)
+            }
+            "--snoozed" => {
+                if !contents.is_empty() {
+                    return Err(HeapError::DoesNotTakeArg("snoozed".to_owned()));
+                }
+                Snoozed
+            }
+            "--tree" => {
+                if !contents.is_empty() {
+                    return Err(HeapError::DoesNotTakeArg("tree".to_owned()));
+                }
+                Tree
+            }
             "-l" | "--list" => {
                 if !contents.is_empty() {
                     return Err(HeapError::DoesNotTakeArg("list".to_owned()));
                 }
                 List
             }
+            "--done" => {
+                if !contents.is_empty() {
+                    return Err(HeapError::DoesNotTakeArg("done".to_owned()));
+                }
+                Done
+            }
+            "--stats" => match contents.as_str() {
+                "" => Stats(false),
+                "json" => Stats(true),
+                _ => {
+                    return Err(HeapError::InvalidArgument((
+                        contents.to_owned(),
+                        "stats".to_owned(),
+                    )));
+                }
+            },
+            "--since" => Since(day_bounds(&contents, "since")?.0),
+            "--until" => Until(day_bounds(&contents, "until")?.1),
             "-h" | "--help" => {
                 if !contents.is_empty() {
                     return Err(HeapError::DoesNotTakeArg("help".to_owned()));
@@ -401,5 +1015,440 @@ fn main() -> Result<(), HeapError> {
             }
         });
     }
-    run_commands(commands)
+    let config = Config::load()?;
+    // Notices go to stderr, so they never end up in output piped to other tools.
+    let heaps = HeapRegistry::new(&config).with_notify(|notice| eprintln!("{notice}"));
+    // The same seed and heap always pop the same tasks.
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let heap_name = match commands.iter().position(|cmd| matches!(cmd, MetaPop(_))) {
+        // A meta-pop chooses the heap for the whole run.
+        Some(index) => {
+            if selected_heap.is_some() {
+                return Err(HeapError::ConflictingArgs((
+                    "meta-pop".to_owned(),
+                    "heap".to_owned(),
+                )));
+            }
+            let tags = match commands.get(index + 1) {
+                Some(Tag(tags)) => tags.as_slice(),
+                _ => &[],
+            };
+            let heap_name = heaps.pick(tags, &mut rng)?;
+            println!("The heap popped first is \"{heap_name}\".");
+            heap_name
+        }
+        None => selected_heap.unwrap_or(config.default_heap),
+    };
+    // Without a terminal to answer on, nothing is confirmed unless --yes says so.
+    let mut prompter: Box<dyn Prompter> = match answer {
+        Some(true) => Box::new(AlwaysYes),
+        Some(false) => Box::new(AlwaysNo),
+        None if stdin().is_terminal() => Box::new(Interactive),
+        None => Box::new(AlwaysNo),
+    };
+    let mut task_heap = heaps.open_heap(&heap_name)?;
+    run_commands(
+        commands,
+        &mut task_heap,
+        &heaps,
+        &heap_name,
+        prompter.as_mut(),
+        &mut rng,
+        &strategy.unwrap_or(config.selection),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use task_heap::{
+        backup::Backups,
+        journal::Journal,
+        prompt::Scripted,
+        storage::{Backend, DEFAULT_HEAP, MemoryStorage},
+    };
+
+    fn run(commands: Vec<Commands>, task_heap: &mut TaskHeap) -> Result<(), HeapError> {
+        run_answering(commands, task_heap, &[])
+    }
+
+    fn run_answering(
+        commands: Vec<Commands>,
+        task_heap: &mut TaskHeap,
+        answers: &[bool],
+    ) -> Result<(), HeapError> {
+        let config = Config {
+            backend: Backend::Memory,
+            ..Config::default()
+        };
+        let heaps = HeapRegistry::in_dir(".", &config);
+        run_commands(
+            commands,
+            task_heap,
+            &heaps,
+            DEFAULT_HEAP,
+            &mut Scripted::new(answers.iter().copied()),
+            &mut StdRng::seed_from_u64(0),
+            &Selection::Linear,
+        )
+    }
+
+    fn memory_heap() -> TaskHeap {
+        TaskHeap::open(
+            Box::new(MemoryStorage::default()),
+            Journal::in_memory(),
+            Backups::disabled(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn run_commands_against_memory_storage() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![
+                Push("Clean the garage".to_owned()),
+                Weight("5".to_owned()),
+                Tag(vec!["home".to_owned()]),
+                Push("Write tests".to_owned()),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        run(
+            vec![
+                Edit("Clean the garage".to_owned()),
+                Name("Clean the house".to_owned()),
+                ClearTags("Clean the house".to_owned()),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+
+        assert_eq!(task_heap.len(), 2);
+        let task = task_heap.get("Clean the house").unwrap();
+        assert_eq!(task.get_weight(), 5);
+        assert!(task.get_tags().is_empty());
+    }
+
+    #[test]
+    fn duplicate_push_leaves_storage_untouched() {
+        let mut task_heap = memory_heap();
+        run(vec![Push("Write tests".to_owned())], &mut task_heap).unwrap();
+        let result = run(vec![Push("Write tests".to_owned())], &mut task_heap);
+        assert!(matches!(result, Err(HeapError::TaskAlreadyExists(_))));
+        assert_eq!(task_heap.len(), 1);
+    }
+
+    #[test]
+    fn invalid_weight_leaves_the_task_untouched() {
+        let mut task_heap = memory_heap();
+        run(vec![Push("Write tests".to_owned())], &mut task_heap).unwrap();
+        let result = run(
+            vec![
+                Edit("Write tests".to_owned()),
+                Name("Write more tests".to_owned()),
+                Weight("lots".to_owned()),
+            ],
+            &mut task_heap,
+        );
+        assert!(matches!(result, Err(HeapError::InvalidArgument(_))));
+        let task = task_heap.get("Write tests").unwrap();
+        assert_eq!(task.get_weight(), task_heap::task::DEFAULT_WEIGHT);
+        assert_eq!(task_heap.history(10).len(), 1);
+    }
+
+    #[test]
+    fn confirmations_come_from_the_prompter() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![
+                Push("a".to_owned()),
+                Tag(vec!["x".to_owned()]),
+                Push("b".to_owned()),
+                Tag(vec!["x".to_owned()]),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+
+        // Refused: the pop is chickened out of and the delete is not done.
+        run_answering(
+            vec![Pop(1), Delete(String::new()), Tag(vec!["x".to_owned()])],
+            &mut task_heap,
+            &[false],
+        )
+        .unwrap();
+        assert_eq!(task_heap.len(), 2);
+
+        run_answering(vec![Pop(1), Reset], &mut task_heap, &[true, false]).unwrap();
+        assert_eq!(task_heap.len(), 1);
+        run_answering(vec![Reset], &mut task_heap, &[true]).unwrap();
+        assert!(task_heap.is_empty());
+    }
+
+    #[test]
+    fn popping_several_asks_for_the_batch_then_each_task() {
+        let mut task_heap = memory_heap();
+        let names = ["a", "b", "c", "d"];
+        run(
+            names.iter().map(|name| Push(name.to_string())).collect(),
+            &mut task_heap,
+        )
+        .unwrap();
+
+        // The batch is refused, then the first task accepted and the second refused.
+        run_answering(vec![Pop(2)], &mut task_heap, &[false, true, false]).unwrap();
+        assert_eq!(task_heap.len(), 3);
+        let rejections: u32 = names
+            .iter()
+            .filter_map(|name| task_heap.get(name))
+            .map(|task| task.get_rejections())
+            .sum();
+        assert_eq!(rejections, 1);
+
+        run_answering(vec![Pop(5)], &mut task_heap, &[true]).unwrap();
+        assert!(task_heap.is_empty());
+    }
+
+    #[test]
+    fn completed_tasks_are_listed_by_tag_and_day() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![
+                Push("a".to_owned()),
+                Tag(vec!["x".to_owned()]),
+                Push("b".to_owned()),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        run_answering(vec![Pop(2)], &mut task_heap, &[true]).unwrap();
+        assert_eq!(task_heap.completions().query(&[], None, None).len(), 2);
+        let tagged = task_heap.completions().query(&["x".to_owned()], None, None);
+        assert_eq!(tagged[0].task.get_name(), "a");
+
+        let (start, end) = day_bounds("2020-01-31", "since").unwrap();
+        assert!(end - start >= 23 * 3600);
+        assert!(
+            task_heap
+                .completions()
+                .query(&[], None, Some(end))
+                .is_empty()
+        );
+        assert!(day_bounds("2026-13-01", "since").is_err());
+        run(
+            vec![Done, Tag(vec!["x".to_owned()]), Since(start), Until(end)],
+            &mut task_heap,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn recurring_tasks_stay_on_the_heap_when_popped() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![
+                Push("water plants".to_owned()),
+                Repeat(Some("every:2".parse().unwrap())),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        run_answering(vec![Pop(1)], &mut task_heap, &[true]).unwrap();
+        let task = task_heap.get("water plants").unwrap();
+        assert!(task.is_hidden(Utc::now().timestamp()));
+        assert_eq!(task_heap.completions().all().len(), 1);
+        let result = run(vec![Pop(1)], &mut task_heap);
+        assert!(matches!(result, Err(HeapError::NoTasksReady)));
+
+        run(
+            vec![Edit("water plants".to_owned()), Repeat(None)],
+            &mut task_heap,
+        )
+        .unwrap();
+        assert!(
+            task_heap
+                .get("water plants")
+                .unwrap()
+                .get_recurrence()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn tasks_pushed_after_others_wait_for_them() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![
+                Push("buy paint".to_owned()),
+                Push("paint fence".to_owned()),
+                After("buy paint".to_owned()),
+                List,
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        let result = run(
+            vec![
+                Edit("buy paint".to_owned()),
+                After("paint fence".to_owned()),
+            ],
+            &mut task_heap,
+        );
+        assert!(matches!(result, Err(HeapError::DependencyCycle(_))));
+        for _ in 0..3 {
+            run_answering(vec![Pop(1)], &mut task_heap, &[false]).unwrap();
+        }
+        assert_eq!(task_heap.get("paint fence").unwrap().get_rejections(), 0);
+
+        run_answering(vec![Pop(1)], &mut task_heap, &[true]).unwrap();
+        assert!(task_heap.blocked().is_empty());
+        run(
+            vec![
+                Push("sand fence".to_owned()),
+                Edit("paint fence".to_owned()),
+                After("sand fence".to_owned()),
+                NotAfter("sand fence".to_owned()),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        assert!(task_heap.blocked().is_empty());
+    }
+
+    #[test]
+    fn popping_a_parent_pops_its_subtasks_first() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![
+                Push("finish project".to_owned()),
+                Push("write docs".to_owned()),
+                Parent("finish project".to_owned()),
+                List,
+                Tree,
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        run_answering(vec![Pop(1)], &mut task_heap, &[true]).unwrap();
+        assert!(task_heap.is_empty());
+        let done: Vec<&str> = task_heap
+            .completions()
+            .all()
+            .iter()
+            .map(|completion| completion.task.get_name())
+            .collect();
+        assert_eq!(done, vec!["write docs", "finish project"]);
+
+        task_heap.undo(1).unwrap();
+        run(
+            vec![Edit("write docs".to_owned()), NoParent],
+            &mut task_heap,
+        )
+        .unwrap();
+        assert!(task_heap.get("write docs").unwrap().get_parent().is_none());
+    }
+
+    #[test]
+    fn tasks_can_be_named_by_id() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![
+                Push("call Bob".to_owned()),
+                Push("taxes".to_owned()),
+                Tag(vec!["home".to_owned()]),
+                Edit("1".to_owned()),
+                Name("call Alice".to_owned()),
+                ClearTags("2".to_owned()),
+                List,
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        assert_eq!(task_heap.get("call Alice").unwrap().get_id(), 1);
+        assert!(task_heap.get("taxes").unwrap().get_tags().is_empty());
+        run_answering(vec![Delete("2".to_owned())], &mut task_heap, &[true]).unwrap();
+        assert!(task_heap.get("taxes").is_none());
+        let result = run(vec![ClearTags("2".to_owned())], &mut task_heap);
+        assert!(matches!(result, Err(HeapError::TaskNotFound(_))));
+
+        run(
+            vec![
+                Push("file taxes".to_owned()),
+                After("1".to_owned()),
+                Push("find receipts".to_owned()),
+                Parent("3".to_owned()),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        assert_eq!(
+            task_heap.get("file taxes").unwrap().get_prerequisites(),
+            ["call Alice"]
+        );
+        assert_eq!(
+            task_heap.get("find receipts").unwrap().get_parent(),
+            Some("file taxes")
+        );
+        run(
+            vec![
+                Edit("3".to_owned()),
+                NotAfter("1".to_owned()),
+                Edit("4".to_owned()),
+                NoParent,
+                Parent("1".to_owned()),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        assert!(task_heap.blocked().is_empty());
+        assert_eq!(
+            task_heap.get("find receipts").unwrap().get_parent(),
+            Some("call Alice")
+        );
+    }
+
+    #[test]
+    fn snoozing_hides_a_task_until_the_time_passes() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![Push("call Bob".to_owned()), Push("taxes".to_owned())],
+            &mut task_heap,
+        )
+        .unwrap();
+        let until = Utc::now().timestamp() + 3600;
+        run(
+            vec![Snooze("call Bob".to_owned(), until), List, List, Snoozed],
+            &mut task_heap,
+        )
+        .unwrap();
+        assert_eq!(task_heap.get("call Bob").unwrap().get_hidden_until(), until);
+        run_answering(vec![Pop(1)], &mut task_heap, &[true]).unwrap();
+        assert!(task_heap.get("taxes").is_none());
+        let result = run(vec![Pop(1)], &mut task_heap);
+        assert!(matches!(result, Err(HeapError::NoTasksReady)));
+    }
+
+    #[test]
+    fn peeking_neither_prompts_nor_removes() {
+        let mut task_heap = memory_heap();
+        run(
+            vec![
+                Push("a".to_owned()),
+                Tag(vec!["x".to_owned()]),
+                Push("b".to_owned()),
+            ],
+            &mut task_heap,
+        )
+        .unwrap();
+        run(vec![Peek, Peek, Tag(vec!["x".to_owned()])], &mut task_heap).unwrap();
+        assert_eq!(task_heap.len(), 2);
+        assert_eq!(task_heap.history(10).len(), 2);
+        let result = run(vec![Peek, Tag(vec!["y".to_owned()])], &mut task_heap);
+        assert!(matches!(result, Err(HeapError::NoTaggedElements(_))));
+    }
 }
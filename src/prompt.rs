@@ -0,0 +1,72 @@
+use crate::HeapError;
+use std::{
+    collections::VecDeque,
+    io::{Write, stdin, stdout},
+};
+
+// Answers the yes/no questions asked before a task is popped or anything is deleted.
+// `question` is the text to show; implementations add the `[y/n]` prompt.
+pub trait Prompter {
+    fn confirm(&mut self, question: &str) -> Result<bool, HeapError>;
+}
+
+// Asks on the terminal.
+pub struct Interactive;
+impl Prompter for Interactive {
+    fn confirm(&mut self, question: &str) -> Result<bool, HeapError> {
+        print!("{question}");
+        Ok(get_yes_no()?.to_lowercase() == "y")
+    }
+}
+
+pub struct AlwaysYes;
+impl Prompter for AlwaysYes {
+    fn confirm(&mut self, question: &str) -> Result<bool, HeapError> {
+        Ok(answer(question, true))
+    }
+}
+
+pub struct AlwaysNo;
+impl Prompter for AlwaysNo {
+    fn confirm(&mut self, question: &str) -> Result<bool, HeapError> {
+        Ok(answer(question, false))
+    }
+}
+
+// Gives the answers in order, then refuses everything once they run out.
+pub struct Scripted {
+    answers: VecDeque<bool>,
+}
+impl Scripted {
+    pub fn new(answers: impl IntoIterator<Item = bool>) -> Self {
+        Self {
+            answers: answers.into_iter().collect(),
+        }
+    }
+}
+impl Prompter for Scripted {
+    fn confirm(&mut self, question: &str) -> Result<bool, HeapError> {
+        Ok(answer(question, self.answers.pop_front().unwrap_or(false)))
+    }
+}
+
+// Shows the question with the answer given on the user's behalf.
+fn answer(question: &str, yes: bool) -> bool {
+    println!("{question}[y/n]: {}", if yes { "y" } else { "n" });
+    yes
+}
+
+fn get_yes_no() -> Result<String, HeapError> {
+    print!("[y/n]: ");
+    stdout().flush().unwrap(); //Flush so prompt appears before user input.
+
+    let mut input = String::new();
+
+    match stdin().read_line(&mut input) {
+        Ok(_) => {
+            input = input.trim().to_owned();
+            Ok(input)
+        }
+        Err(e) => Err(HeapError::FileError(e)),
+    }
+}
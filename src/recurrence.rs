@@ -0,0 +1,134 @@
+use crate::{
+    HeapError,
+    dates::{local_day, start_of_day},
+};
+use chrono::{Datelike, Days, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+// When a recurring task comes back after it is done. Written as `daily`, `weekly`,
+// `every:<days>` or `weekdays:<day>[,<day>...]` with English day names, as in
+// `weekdays:mon,thu`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Recurrence {
+    Daily,
+    Weekly,
+    EveryDays(u32),
+    // Indexed from Monday.
+    Weekdays([bool; 7]),
+}
+impl Recurrence {
+    // Start of the local day the task is due again, when done at unix time `now`.
+    pub fn next_due(&self, now: i64) -> i64 {
+        let today = local_day(now);
+        let days_ahead = match self {
+            Recurrence::Daily => 1,
+            Recurrence::Weekly => 7,
+            Recurrence::EveryDays(days) => (*days).into(),
+            Recurrence::Weekdays(days) => {
+                let today = today.weekday().num_days_from_monday() as usize;
+                (1..=7)
+                    .find(|ahead| days[(today + *ahead as usize) % 7])
+                    .unwrap_or(7)
+            }
+        };
+        today
+            .checked_add_days(Days::new(days_ahead))
+            .and_then(start_of_day)
+            .unwrap_or(now)
+    }
+}
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly => write!(f, "weekly"),
+            Recurrence::EveryDays(days) => write!(f, "every:{days}"),
+            Recurrence::Weekdays(days) => {
+                let names: Vec<String> = WEEKDAYS
+                    .iter()
+                    .zip(days)
+                    .filter(|(_, on)| **on)
+                    .map(|(day, _)| day.to_string().to_lowercase())
+                    .collect();
+                write!(f, "weekdays:{}", names.join(","))
+            }
+        }
+    }
+}
+impl FromStr for Recurrence {
+    type Err = HeapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || HeapError::InvalidArgument((s.to_owned(), "repeat".to_owned()));
+        let rule = s.trim().to_lowercase();
+        let (name, parameter) = match rule.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (rule.as_str(), None),
+        };
+        match (name, parameter) {
+            ("daily", None) => Ok(Recurrence::Daily),
+            ("weekly", None) => Ok(Recurrence::Weekly),
+            ("every", Some(days)) => match days.parse() {
+                Ok(days) if days > 0 => Ok(Recurrence::EveryDays(days)),
+                _ => Err(invalid()),
+            },
+            ("weekdays", Some(names)) => {
+                let mut days = [false; 7];
+                for name in names.split(',') {
+                    let day: Weekday = name.trim().parse().map_err(|_| invalid())?;
+                    days[day.num_days_from_monday() as usize] = true;
+                }
+                Ok(Recurrence::Weekdays(days))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+impl From<Recurrence> for String {
+    fn from(recurrence: Recurrence) -> Self {
+        recurrence.to_string()
+    }
+}
+impl TryFrom<String> for Recurrence {
+    type Error = HeapError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn rules_round_trip_and_schedule_the_next_day_due() {
+        for rule in ["daily", "weekly", "every:3", "weekdays:mon,thu"] {
+            assert_eq!(rule.parse::<Recurrence>().unwrap().to_string(), rule);
+        }
+        assert!("every:0".parse::<Recurrence>().is_err());
+        assert!("weekdays:someday".parse::<Recurrence>().is_err());
+
+        // A Friday afternoon.
+        let friday = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let now = start_of_day(friday).unwrap() + 15 * 3600;
+        let due = |rule: &str| local_day(rule.parse::<Recurrence>().unwrap().next_due(now));
+        assert_eq!(due("daily"), friday + Days::new(1));
+        assert_eq!(due("every:3"), friday + Days::new(3));
+        assert_eq!(due("weekdays:mon,thu"), friday + Days::new(3));
+        assert_eq!(due("weekdays:fri"), friday + Days::new(7));
+    }
+}
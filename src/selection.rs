@@ -0,0 +1,334 @@
+use crate::{HeapError, Task};
+use rand::{
+    Rng, RngCore,
+    distributions::{WeightedIndex, uniform::SampleUniform},
+    prelude::Distribution,
+};
+use std::{ops::AddAssign, str::FromStr};
+
+const DEFAULT_TEMPERATURE: f64 = 1.0;
+
+// A task a pop may offer, with the weight it competes with.
+pub struct Candidate<'a> {
+    pub task: &'a Task,
+    pub weight: u32,
+}
+
+// Decides which of the candidates a pop offers.
+pub trait SelectionStrategy {
+    // `candidates` is never empty and is sorted by name; the result is an index into it,
+    // or `NoTasksReady` when none of them can be drawn, e.g. all weigh 0.
+    // `previous` is the task popped last from this heap, if the journal still knows it.
+    fn choose(
+        &self,
+        candidates: &[Candidate],
+        previous: Option<&Task>,
+        rng: &mut dyn RngCore,
+    ) -> Result<usize, HeapError>;
+    // The chance `choose` has of returning each candidate, in candidate order.
+    fn probabilities(&self, candidates: &[Candidate], previous: Option<&Task>) -> Vec<f64>;
+}
+
+// The built-in strategies, named in the config file or with --strategy as `linear`,
+// `softmax` (or `softmax:<temperature>`), `uniform`, `highest` and `round-robin`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Selection {
+    // Chance proportional to weight.
+    #[default]
+    Linear,
+    // Chance proportional to exp(weight / largest weight / temperature): low
+    // temperatures favour the heaviest tasks, high ones flatten towards uniform.
+    Softmax(f64),
+    Uniform,
+    // Always the heaviest task, the first by name on ties.
+    Highest,
+    // Takes turns between tags: the tag after the previously popped task's tag, then
+    // by weight within it. Untagged tasks take their turn before the first tag.
+    RoundRobin,
+}
+impl SelectionStrategy for Selection {
+    fn choose(
+        &self,
+        candidates: &[Candidate],
+        previous: Option<&Task>,
+        rng: &mut dyn RngCore,
+    ) -> Result<usize, HeapError> {
+        match self {
+            Selection::Linear => {
+                let weights: Vec<u32> = candidates
+                    .iter()
+                    .map(|candidate| candidate.weight)
+                    .collect();
+                sample(&weights, rng)
+            }
+            Selection::Softmax(temperature) => sample(&softmax(candidates, *temperature), rng),
+            Selection::Uniform => Ok(rng.gen_range(0..candidates.len())),
+            Selection::Highest => Ok(highest(candidates)),
+            Selection::RoundRobin => {
+                let members = next_group(candidates, previous);
+                let weights: Vec<u32> = members
+                    .iter()
+                    .map(|index| candidates[*index].weight)
+                    .collect();
+                Ok(members[sample(&weights, rng)?])
+            }
+        }
+    }
+    fn probabilities(&self, candidates: &[Candidate], previous: Option<&Task>) -> Vec<f64> {
+        match self {
+            Selection::Linear => shares(
+                candidates
+                    .iter()
+                    .map(|candidate| candidate.weight as f64)
+                    .collect(),
+            ),
+            Selection::Softmax(temperature) => shares(softmax(candidates, *temperature)),
+            Selection::Uniform => vec![1.0 / candidates.len() as f64; candidates.len()],
+            Selection::Highest => {
+                let mut probabilities = vec![0.0; candidates.len()];
+                probabilities[highest(candidates)] = 1.0;
+                probabilities
+            }
+            Selection::RoundRobin => {
+                let mut weights = vec![0.0; candidates.len()];
+                for index in next_group(candidates, previous) {
+                    weights[index] = candidates[index].weight as f64;
+                }
+                shares(weights)
+            }
+        }
+    }
+}
+impl FromStr for Selection {
+    type Err = HeapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let (name, parameter) = match s.split_once(':') {
+            Some((name, parameter)) => (name, Some(parameter)),
+            None => (s.as_str(), None),
+        };
+        match (name, parameter) {
+            ("linear", None) => Ok(Selection::Linear),
+            ("softmax", None) => Ok(Selection::Softmax(DEFAULT_TEMPERATURE)),
+            ("softmax", Some(temperature)) => match temperature.parse::<f64>() {
+                Ok(temperature) if temperature > 0.0 && temperature.is_finite() => {
+                    Ok(Selection::Softmax(temperature))
+                }
+                _ => Err(HeapError::InvalidConfig(format!(
+                    "softmax temperature \"{temperature}\" is not a positive number"
+                ))),
+            },
+            ("uniform", None) => Ok(Selection::Uniform),
+            ("highest", None) => Ok(Selection::Highest),
+            ("round-robin", None) => Ok(Selection::RoundRobin),
+            _ => Err(HeapError::InvalidConfig(format!(
+                "unknown selection strategy \"{s}\""
+            ))),
+        }
+    }
+}
+
+fn softmax(candidates: &[Candidate], temperature: f64) -> Vec<f64> {
+    let largest = candidates.iter().map(|candidate| candidate.weight).max();
+    let largest = largest.unwrap_or_default().max(1) as f64;
+    candidates
+        .iter()
+        .map(|candidate| ((candidate.weight as f64 / largest - 1.0) / temperature).exp())
+        .collect()
+}
+
+fn highest(candidates: &[Candidate]) -> usize {
+    let mut best = 0;
+    for (index, candidate) in candidates.iter().enumerate() {
+        if candidate.weight > candidates[best].weight {
+            best = index;
+        }
+    }
+    best
+}
+
+// Indices of the candidates in the tag group whose turn it is. Groups that weigh
+// nothing in total sit their turn out; when all do, there is no group.
+fn next_group(candidates: &[Candidate], previous: Option<&Task>) -> Vec<usize> {
+    let group_of = |task: &Task| {
+        let mut tags = task.get_tags();
+        tags.sort();
+        tags.first().copied().unwrap_or_default().to_owned()
+    };
+    let mut groups: Vec<String> = candidates
+        .iter()
+        .filter(|candidate| candidate.weight > 0)
+        .map(|candidate| group_of(candidate.task))
+        .collect();
+    groups.sort();
+    groups.dedup();
+    let Some(first) = groups.first() else {
+        return Vec::new();
+    };
+    let next = previous
+        .map(group_of)
+        .and_then(|last| groups.iter().find(|group| **group > last))
+        .unwrap_or(first);
+    (0..candidates.len())
+        .filter(|index| group_of(candidates[*index].task) == *next)
+        .collect()
+}
+
+// Weights scaled to sum to one. Weights that are all zero stay zero, like a pop
+// that has nothing it can draw.
+fn shares(weights: Vec<f64>) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    if total <= 0.0 {
+        return weights;
+    }
+    weights.into_iter().map(|weight| weight / total).collect()
+}
+
+// Zero weights are never drawn; when there is nothing else, nothing can be.
+fn sample<W>(weights: &[W], rng: &mut dyn RngCore) -> Result<usize, HeapError>
+where
+    W: SampleUniform + PartialOrd + for<'a> AddAssign<&'a W> + Clone + Default,
+{
+    let distribution = WeightedIndex::new(weights).map_err(|_| HeapError::NoTasksReady)?;
+    Ok(distribution.sample(rng))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn task(name: &str, weight: u32, tags: &[&str]) -> Task {
+        let mut task = Task::from_arg(name);
+        task.set_weight(weight.to_string()).unwrap();
+        task.add_tags(tags.iter().map(|tag| tag.to_string()).collect());
+        task
+    }
+
+    fn candidates(tasks: &[Task]) -> Vec<Candidate<'_>> {
+        tasks
+            .iter()
+            .map(|task| Candidate {
+                task,
+                weight: task.get_weight(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strategies_parse_from_config_values() {
+        assert_eq!("linear".parse::<Selection>().unwrap(), Selection::Linear);
+        assert_eq!(
+            "softmax:0.5".parse::<Selection>().unwrap(),
+            Selection::Softmax(0.5)
+        );
+        assert_eq!(
+            " Round-Robin ".parse::<Selection>().unwrap(),
+            Selection::RoundRobin
+        );
+        assert!("softmax:-1".parse::<Selection>().is_err());
+        assert!("uniform:2".parse::<Selection>().is_err());
+    }
+
+    #[test]
+    fn highest_and_cold_softmax_pick_the_heaviest_task() {
+        let tasks = [task("a", 10, &[]), task("b", 90, &[]), task("c", 90, &[])];
+        let candidates = candidates(&tasks);
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(
+            Selection::Highest
+                .choose(&candidates, None, &mut rng)
+                .unwrap(),
+            1
+        );
+        for _ in 0..20 {
+            let chosen = Selection::Softmax(0.01)
+                .choose(&candidates, None, &mut rng)
+                .unwrap();
+            assert_ne!(chosen, 0);
+        }
+    }
+
+    #[test]
+    fn round_robin_takes_turns_between_tags() {
+        let tasks = [
+            task("a", 100, &["home"]),
+            task("b", 100, &["work"]),
+            task("c", 100, &[]),
+        ];
+        let candidates = candidates(&tasks);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut previous = None;
+        let mut chosen = Vec::new();
+        for _ in 0..4 {
+            let index = Selection::RoundRobin
+                .choose(&candidates, previous, &mut rng)
+                .unwrap();
+            chosen.push(tasks[index].get_name());
+            previous = Some(&tasks[index]);
+        }
+        assert_eq!(chosen, ["c", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn probabilities_match_each_strategy() {
+        let tasks = [
+            task("a", 10, &["home"]),
+            task("b", 30, &["home"]),
+            task("c", 60, &["work"]),
+        ];
+        let candidates = candidates(&tasks);
+        let close = |actual: Vec<f64>, expected: [f64; 3]| {
+            for (actual, expected) in actual.iter().zip(expected) {
+                assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+            }
+        };
+        close(
+            Selection::Linear.probabilities(&candidates, None),
+            [0.1, 0.3, 0.6],
+        );
+        close(
+            Selection::Highest.probabilities(&candidates, None),
+            [0.0, 0.0, 1.0],
+        );
+        close(
+            Selection::RoundRobin.probabilities(&candidates, Some(&tasks[2])),
+            [0.25, 0.75, 0.0],
+        );
+        let softmax = Selection::Softmax(0.5).probabilities(&candidates, None);
+        assert!((softmax.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(softmax[0] < softmax[1] && softmax[1] < softmax[2]);
+    }
+
+    #[test]
+    fn weightless_tasks_are_never_drawn() {
+        let tasks = [
+            task("a", 100, &["home"]),
+            task("b", 0, &["work"]),
+            task("c", 100, &["home"]),
+        ];
+        let ready = candidates(&tasks);
+        let mut rng = StdRng::seed_from_u64(1);
+        // The work group weighs nothing, so its turn goes back to home.
+        for _ in 0..20 {
+            let index = Selection::RoundRobin
+                .choose(&ready, Some(&tasks[0]), &mut rng)
+                .unwrap();
+            assert_ne!(index, 1);
+        }
+        assert_eq!(
+            Selection::RoundRobin.probabilities(&ready, Some(&tasks[0])),
+            [0.5, 0.0, 0.5]
+        );
+
+        let tasks = [task("a", 0, &["home"]), task("b", 0, &[])];
+        let weightless = candidates(&tasks);
+        for strategy in [Selection::Linear, Selection::RoundRobin] {
+            assert!(matches!(
+                strategy.choose(&weightless, None, &mut rng),
+                Err(HeapError::NoTasksReady)
+            ));
+        }
+    }
+}
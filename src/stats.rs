@@ -0,0 +1,195 @@
+use crate::{Task, completion::Completion, dates::local_day};
+use chrono::{Datelike, Days, NaiveDate};
+use serde::Serialize;
+use std::{cmp::Reverse, collections::BTreeMap};
+
+const DAYS_SHOWN: u64 = 14;
+const WEEKS_SHOWN: u64 = 8;
+const MOST_AVOIDED_SHOWN: usize = 5;
+
+#[derive(Serialize)]
+pub struct DayCount {
+    pub day: String,
+    pub completed: usize,
+}
+
+#[derive(Serialize)]
+pub struct AvoidedTask {
+    pub name: String,
+    pub rejections: u32,
+    pub completed: bool,
+}
+
+#[derive(Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub completed: usize,
+    pub last_week: usize,
+}
+
+// A report on one heap's completion log and the rejections of its tasks, done or not.
+// Days are local days; weeks start on Monday and are named after it.
+#[derive(Serialize)]
+pub struct Stats {
+    // The last 14 days and 8 weeks, oldest first, including the empty ones.
+    pub per_day: Vec<DayCount>,
+    pub per_week: Vec<DayCount>,
+    pub penguins: usize,
+    pub chickens: u32,
+    // Consecutive days with a completion, up to today. A day without one yet does
+    // not break the streak until it is over.
+    pub current_streak: usize,
+    pub longest_streak: usize,
+    pub most_avoided: Vec<AvoidedTask>,
+    // Tags by completions, most first.
+    pub per_tag: Vec<TagCount>,
+}
+impl Stats {
+    pub fn compute(completions: &[Completion], open: &[&Task], today: NaiveDate) -> Self {
+        let mut days: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+        for completion in completions {
+            *days.entry(local_day(completion.completed)).or_default() += 1;
+        }
+        let count_between = |first: NaiveDate, last: NaiveDate| -> usize {
+            days.range(first..=last).map(|(_, count)| count).sum()
+        };
+
+        let per_day = (0..DAYS_SHOWN)
+            .rev()
+            .filter_map(|back| today.checked_sub_days(Days::new(back)))
+            .map(|day| DayCount {
+                day: day.to_string(),
+                completed: count_between(day, day),
+            })
+            .collect();
+        let this_monday = today
+            .checked_sub_days(Days::new(today.weekday().num_days_from_monday().into()))
+            .unwrap_or(today);
+        let per_week = (0..WEEKS_SHOWN)
+            .rev()
+            .filter_map(|back| this_monday.checked_sub_days(Days::new(7 * back)))
+            .map(|monday| DayCount {
+                day: monday.to_string(),
+                completed: count_between(monday, monday + Days::new(6)),
+            })
+            .collect();
+
+        let (mut longest_streak, mut streak, mut previous) = (0, 0, None);
+        for day in days.keys() {
+            streak = match previous {
+                Some(previous) if previous + Days::new(1) == *day => streak + 1,
+                _ => 1,
+            };
+            longest_streak = longest_streak.max(streak);
+            previous = Some(*day);
+        }
+        let yesterday = today.pred_opt().unwrap_or(today);
+        let current_streak = match previous {
+            Some(last) if last == today || last == yesterday => streak,
+            _ => 0,
+        };
+
+        let mut most_avoided: Vec<AvoidedTask> = open
+            .iter()
+            .map(|task| (*task, false))
+            .chain(
+                completions
+                    .iter()
+                    .map(|completion| (&completion.task, true)),
+            )
+            .filter(|(task, _)| task.get_rejections() > 0)
+            .map(|(task, completed)| AvoidedTask {
+                name: task.get_name().to_owned(),
+                rejections: task.get_rejections(),
+                completed,
+            })
+            .collect();
+        most_avoided.sort_by(|a, b| b.rejections.cmp(&a.rejections).then(a.name.cmp(&b.name)));
+        let chickens = most_avoided.iter().map(|task| task.rejections).sum();
+        most_avoided.truncate(MOST_AVOIDED_SHOWN);
+
+        let week_ago = today.checked_sub_days(Days::new(6)).unwrap_or(today);
+        let mut tags: BTreeMap<&str, TagCount> = BTreeMap::new();
+        for completion in completions {
+            let recent = local_day(completion.completed) >= week_ago;
+            for tag in completion.task.get_tags() {
+                let count = tags.entry(tag).or_insert_with(|| TagCount {
+                    tag: tag.to_owned(),
+                    completed: 0,
+                    last_week: 0,
+                });
+                count.completed += 1;
+                count.last_week += usize::from(recent);
+            }
+        }
+        let mut per_tag: Vec<TagCount> = tags.into_values().collect();
+        per_tag.sort_by_key(|tag| Reverse(tag.completed));
+
+        Self {
+            per_day,
+            per_week,
+            penguins: completions.len(),
+            chickens,
+            current_streak,
+            longest_streak,
+            most_avoided,
+            per_tag,
+        }
+    }
+    // Share of pops that were accepted, from 0 to 1, or None before the first pop.
+    pub fn penguin_ratio(&self) -> Option<f64> {
+        let pops = self.penguins as f64 + self.chickens as f64;
+        (pops > 0.0).then(|| self.penguins as f64 / pops)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::start_of_day;
+
+    fn completed(name: &str, day: NaiveDate, tags: &[&str], rejections: u32) -> Completion {
+        let mut task = Task::from_arg(name);
+        task.add_tags(tags.iter().map(|tag| tag.to_string()).collect())
+            .set_rejections(rejections);
+        Completion {
+            completed: start_of_day(day).unwrap() + 12 * 3600,
+            task,
+        }
+    }
+
+    #[test]
+    fn streaks_ratios_and_tags_come_from_the_log() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 15).unwrap();
+        let day = |back: u64| today - Days::new(back);
+        let completions = [
+            completed("a", day(10), &["home"], 0),
+            completed("b", day(9), &["home"], 2),
+            completed("c", day(8), &[], 0),
+            completed("d", day(3), &["work"], 0),
+            completed("e", day(2), &["work", "home"], 1),
+            completed("f", day(1), &["work"], 0),
+            completed("g", day(1), &[], 0),
+        ];
+        let mut avoided = Task::from_arg("taxes");
+        avoided.set_rejections(5);
+        let stats = Stats::compute(&completions, &[&avoided, &Task::from_arg("x")], today);
+
+        assert_eq!(stats.per_day.len(), 14);
+        assert_eq!(stats.per_day[12].completed, 2);
+        assert_eq!(stats.per_day[13].completed, 0);
+        let weekly: usize = stats.per_week.iter().map(|week| week.completed).sum();
+        assert_eq!(weekly, 7);
+        assert_eq!((stats.current_streak, stats.longest_streak), (3, 3));
+        assert_eq!((stats.penguins, stats.chickens), (7, 8));
+        assert_eq!(stats.most_avoided[0].name, "taxes");
+        assert!(stats.most_avoided[1].completed);
+        assert_eq!(stats.per_tag[0].tag, "home");
+        assert_eq!(stats.per_tag[0].completed, 3);
+        assert_eq!(stats.per_tag[1].last_week, 3);
+
+        let later = Stats::compute(&completions, &[], today + Days::new(2));
+        assert_eq!(later.current_streak, 0);
+        assert!(Stats::compute(&[], &[], today).penguin_ratio().is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{Hash, HeapError, Task, Tasks};
+use directories::ProjectDirs;
+use std::{
+    env, fmt,
+    fs::{self, TryLockError},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+mod csv;
+mod json;
+mod memory;
+pub(crate) mod registry;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub use csv::CsvStorage;
+pub(crate) use csv::{parse_heap as parse_csv_heap, write_heap as write_csv_heap};
+pub use json::JsonStorage;
+pub use memory::MemoryStorage;
+pub use registry::{DEFAULT_HEAP, HeapRegistry};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+// Version of the task record layout, shared by every backend.
+pub const SCHEMA_VERSION: u32 = 7;
+
+pub trait Storage {
+    // Takes the lock `load` would, without reading anything yet. Files kept next to the
+    // database, like the journal, must only be read once it is held.
+    fn lock(&mut self) -> Result<(), HeapError> {
+        Ok(())
+    }
+    // A backend with no database yet returns HeapError::FileDoesNotExist.
+    fn load(&mut self) -> Result<Tasks, HeapError>;
+    // Reads the tasks as `load` would, but without taking the lock or writing a
+    // migration back, to look at a heap another run may be using. Never save the result.
+    fn peek(&mut self) -> Result<Tasks, HeapError> {
+        self.load()
+    }
+    fn save(&mut self, heap: &Tasks) -> Result<(), HeapError>;
+
+    // Incremental updates, called as each task changes. Backends that rewrite the
+    // whole database in `save` can ignore them.
+    fn insert(&mut self, _task: &Task) -> Result<(), HeapError> {
+        Ok(())
+    }
+    fn update(&mut self, _old_hash: &Hash, _task: &Task) -> Result<(), HeapError> {
+        Ok(())
+    }
+    fn remove(&mut self, _hash: &Hash) -> Result<(), HeapError> {
+        Ok(())
+    }
+    fn clear(&mut self) -> Result<(), HeapError> {
+        Ok(())
+    }
+    // Hashes of the tasks that carry every tag in `tags`.
+    fn find_by_tags(&mut self, heap: &Tasks, tags: &[String]) -> Result<Vec<Hash>, HeapError> {
+        Ok(heap
+            .iter()
+            .filter(|(_, task)| task.has_tags(tags))
+            .map(|(hash, _)| *hash)
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Csv,
+    Json,
+    Memory,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+impl FromStr for Backend {
+    type Err = HeapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(Backend::Csv),
+            "json" => Ok(Backend::Json),
+            "memory" => Ok(Backend::Memory),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Backend::Sqlite),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => Err(HeapError::InvalidConfig(
+                "the sqlite backend needs task-heap built with --features sqlite".to_owned(),
+            )),
+            other => Err(HeapError::InvalidConfig(format!(
+                "unknown backend \"{other}\""
+            ))),
+        }
+    }
+}
+
+pub fn get_data_dir() -> PathBuf {
+    match env::var("TASK_HEAP_DBPATH") {
+        Ok(path) => PathBuf::from_str(&path).unwrap(),
+        Err(_) => {
+            if let Some(proj_dirs) = ProjectDirs::from("com", "tobe", "task-heap") {
+                // Get the specific data directory (e.g., AppData/Roaming/task-heap)
+                let data_dir = proj_dirs.data_dir();
+
+                // Create the directory if it doesn't exist (Crucial for first run!)
+                if !data_dir.exists() {
+                    fs::create_dir_all(data_dir).expect("Could not create data directory");
+                }
+                data_dir.to_path_buf()
+            } else {
+                PathBuf::from(".")
+            }
+        }
+    }
+}
+
+// What the storage has to tell the user while it works. The library does not print
+// them: front ends pass a `Notify` callback to the registry or storage that does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notice {
+    // Another process holds the heap's lock, so this one waits for it.
+    WaitingForLock(PathBuf),
+    // The database was upgraded from an older schema; `backup` keeps the original.
+    Migrated { from: u32, backup: PathBuf },
+    // A new SQLite database took in the tasks of the CSV one.
+    Imported { count: usize, from: PathBuf },
+}
+impl fmt::Display for Notice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Notice::WaitingForLock(_) => {
+                write!(
+                    f,
+                    "Waiting for another task-heap process to release the heap..."
+                )
+            }
+            Notice::Migrated { from, backup } => write!(
+                f,
+                "Upgraded the task heap from schema v{from} to v{SCHEMA_VERSION}. The original was saved to {}",
+                backup.display()
+            ),
+            Notice::Imported { count, from } => {
+                write!(f, "Imported {count} tasks from {}", from.display())
+            }
+        }
+    }
+}
+pub type Notify = Rc<dyn Fn(&Notice)>;
+pub(crate) fn ignore_notices() -> Notify {
+    Rc::new(|_| {})
+}
+
+// Exclusive advisory lock on a `.lock` file next to the database, held by the file
+// backends from `load` until they are dropped, so concurrent runs cannot overwrite
+// each other (or interleave their journal entries).
+// The OS releases it when the file is closed, including when the process dies.
+pub(crate) struct FileLock {
+    _file: fs::File,
+}
+impl FileLock {
+    const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+    pub(crate) fn acquire(
+        db_path: &Path,
+        timeout: Duration,
+        notify: &Notify,
+    ) -> Result<Self, HeapError> {
+        let lock_path = lock_path_for(db_path);
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)?;
+        let deadline = Instant::now() + timeout;
+        let mut warned = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    if !warned {
+                        notify(&Notice::WaitingForLock(lock_path.clone()));
+                        warned = true;
+                    }
+                    thread::sleep(Self::RETRY_INTERVAL);
+                }
+                Err(TryLockError::WouldBlock) => return Err(HeapError::HeapLocked(lock_path)),
+                Err(TryLockError::Error(e)) => return Err(HeapError::FileError(e)),
+            }
+        }
+    }
+}
+fn lock_path_for(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".lock");
+    path.with_file_name(file_name)
+}
+
+// The contents are written to a temporary file in the same directory and renamed over
+// the target, so a crash mid-write never leaves a truncated or duplicated database.
+pub(crate) fn write_atomically<F>(path: &Path, write_contents: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<fs::File>) -> io::Result<()>,
+{
+    let tmp_path = tmp_path_for(path);
+    let result =
+        write_tmp_file(&tmp_path, write_contents).and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    sync_parent_dir(path)
+}
+fn tmp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "db".to_owned());
+    path.with_file_name(format!(".{file_name}.{}.tmp", process::id()))
+}
+fn write_tmp_file<F>(path: &Path, write_contents: F) -> io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<fs::File>) -> io::Result<()>,
+{
+    let file: fs::File = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    let mut writer = BufWriter::new(file);
+    write_contents(&mut writer)?;
+    writer.flush()?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()
+}
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    // Persist the rename itself, not only the file contents.
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn a_held_lock_times_out_until_it_is_dropped() {
+        let dir = std::env::temp_dir().join(format!("task-heap-lock-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("default.csv");
+        let timeout = Duration::from_millis(100);
+        let waits = Rc::new(Cell::new(0));
+        let notify: Notify = {
+            let waits = waits.clone();
+            Rc::new(move |_| waits.set(waits.get() + 1))
+        };
+
+        let lock = FileLock::acquire(&db_path, timeout, &notify).unwrap();
+        assert!(matches!(
+            FileLock::acquire(&db_path, timeout, &notify),
+            Err(HeapError::HeapLocked(path)) if path == lock_path_for(&db_path)
+        ));
+        assert_eq!(waits.get(), 1);
+        drop(lock);
+        FileLock::acquire(&db_path, timeout, &notify).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
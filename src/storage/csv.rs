@@ -0,0 +1,306 @@
+use super::{FileLock, Notice, Notify, SCHEMA_VERSION, Storage, ignore_notices, write_atomically};
+use crate::{HeapError, Task, Tasks, backup::Backups, csv, task::DEFAULT_WEIGHT};
+use chrono::Utc;
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// Every database starts with a header record: the magic string and the schema version.
+// Files without one predate versioning and are treated as version 0.
+const MAGIC: &str = "#task-heap";
+type Migration = fn(Vec<String>) -> Vec<String>;
+// MIGRATIONS[n] upgrades a task record from schema version n to n + 1.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    // v0 -> v1: only the header was added, rows keep their columns.
+    |fields| fields,
+    // v1 -> v2: creation time. Existing tasks start aging now.
+    |mut fields| {
+        fields.resize(4, String::new());
+        // Legacy rows could leave out the weight, which always meant the default.
+        if fields[2].is_empty() {
+            fields[2] = DEFAULT_WEIGHT.to_string();
+        }
+        fields.push(Utc::now().timestamp().to_string());
+        fields
+    },
+    // v2 -> v3: rejection count and cooldown.
+    |mut fields| {
+        fields.resize(5, String::new());
+        fields.extend(["0".to_owned(), "0".to_owned()]);
+        fields
+    },
+    // v3 -> v4: recurrence and the time a task is hidden until.
+    |mut fields| {
+        fields.resize(7, String::new());
+        fields.extend([String::new(), "0".to_owned()]);
+        fields
+    },
+    // v4 -> v5: prerequisites.
+    |mut fields| {
+        fields.resize(9, String::new());
+        fields.push(String::new());
+        fields
+    },
+    // v5 -> v6: parent task.
+    |mut fields| {
+        fields.resize(10, String::new());
+        fields.push(String::new());
+        fields
+    },
+    // v6 -> v7: ID. Opening the heap numbers the tasks without one.
+    |mut fields| {
+        fields.resize(11, String::new());
+        fields.push("0".to_owned());
+        fields
+    },
+];
+
+pub struct CsvStorage {
+    path: PathBuf,
+    lock_timeout: Duration,
+    lock: Option<FileLock>,
+    backups: Option<Backups>,
+    notify: Notify,
+}
+impl CsvStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock_timeout: Duration::ZERO,
+            lock: None,
+            backups: None,
+            notify: ignore_notices(),
+        }
+    }
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+    // Where the original file goes before a schema migration. Without it, the
+    // original is kept next to the database as `<file>.v<version>.bak`.
+    pub fn with_backups(mut self, backups: Backups) -> Self {
+        self.backups = Some(backups);
+        self
+    }
+    pub fn with_notify(mut self, notify: Notify) -> Self {
+        self.notify = notify;
+        self
+    }
+}
+impl Storage for CsvStorage {
+    fn lock(&mut self) -> Result<(), HeapError> {
+        if self.lock.is_none() {
+            self.lock = Some(FileLock::acquire(
+                &self.path,
+                self.lock_timeout,
+                &self.notify,
+            )?);
+        }
+        Ok(())
+    }
+    fn load(&mut self) -> Result<Tasks, HeapError> {
+        self.lock()?;
+        if !self.path.exists() {
+            return Err(HeapError::FileDoesNotExist);
+        }
+        let (heap, version) = parse_heap(&fs::read_to_string(&self.path)?)?;
+        if version < SCHEMA_VERSION {
+            let backup_path = match &self.backups {
+                Some(backups) => backups.snapshot_file(&self.path, "migration")?,
+                None => None,
+            };
+            let backup_path = match backup_path {
+                Some(path) => path,
+                None => {
+                    let path = self.path.with_extension(format!("csv.v{version}.bak"));
+                    fs::copy(&self.path, &path)?;
+                    path
+                }
+            };
+            write_heap(&self.path, &heap)?;
+            (self.notify)(&Notice::Migrated {
+                from: version,
+                backup: backup_path,
+            });
+        }
+        Ok(heap)
+    }
+    // Saves replace the file in one rename, so it can be read whole without the lock.
+    fn peek(&mut self) -> Result<Tasks, HeapError> {
+        if !self.path.exists() {
+            return Err(HeapError::FileDoesNotExist);
+        }
+        let (heap, _) = parse_heap(&fs::read_to_string(&self.path)?)?;
+        Ok(heap)
+    }
+    fn save(&mut self, heap: &Tasks) -> Result<(), HeapError> {
+        write_heap(&self.path, heap)
+    }
+}
+
+// Parses a whole CSV database, upgrading rows from older schemas. Also returns the
+// schema version the data was written with.
+pub(crate) fn parse_heap(data: &str) -> Result<(Tasks, u32), HeapError> {
+    // Records can span lines when a quoted field holds a line break,
+    // so the file is split into records rather than read line by line.
+    let mut records = csv::split_records(data).into_iter().peekable();
+    // Only a first field that is exactly the magic string makes a header; a legacy task
+    // may well be named something like `#task-heap ideas`.
+    let first = records.peek().copied().unwrap_or_default();
+    let version = match csv::decode_record(first)?.as_slice() {
+        [magic, version] if magic == MAGIC => {
+            records.next();
+            version
+                .parse()
+                .map_err(|_| HeapError::CorruptData(first.to_string()))?
+        }
+        [magic, ..] if magic == MAGIC => return Err(HeapError::CorruptData(first.to_string())),
+        _ => 0,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(HeapError::UnsupportedSchema(version));
+    }
+    let mut heap = HashMap::new();
+    for record in records {
+        let fields = MIGRATIONS[version as usize..]
+            .iter()
+            .try_fold(csv::decode_record(record)?, |fields, migrate| {
+                Ok::<_, HeapError>(migrate(fields))
+            })?;
+        let new_task = Task::from_fields(&fields)?;
+        heap.insert(new_task.get_hash(), new_task);
+    }
+    Ok((heap, version))
+}
+pub(crate) fn write_heap(path: &Path, heap: &Tasks) -> Result<(), HeapError> {
+    write_atomically(path, |writer| {
+        writeln!(
+            writer,
+            "{}",
+            csv::encode_record([MAGIC, &SCHEMA_VERSION.to_string()])
+        )?;
+        for task in heap.values() {
+            writeln!(writer, "{}", task)?;
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    fn db_in(label: &str, contents: &str) -> (PathBuf, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("task-heap-csv-{label}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("default.csv");
+        fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    fn heap_of(names: &[&str]) -> Tasks {
+        names
+            .iter()
+            .map(|name| {
+                let task = Task::from_arg(*name);
+                (task.get_hash(), task)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn saving_after_a_delete_rewrites_the_whole_file() {
+        let dir =
+            std::env::temp_dir().join(format!("task-heap-csv-rewrite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("default.csv");
+        let mut storage = CsvStorage::new(&path);
+        storage
+            .save(&heap_of(&["Buy milk", "Clean the garage", "Write tests"]))
+            .unwrap();
+        storage
+            .save(&heap_of(&["Buy milk", "Clean the garage"]))
+            .unwrap();
+
+        let saved = storage.load().unwrap();
+        assert_eq!(saved.len(), 2);
+        assert!(!saved.contains_key(&Task::hash_fn("Write tests")));
+        // The header and the two tasks left.
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+        // The temporary file was renamed over the database, not left behind.
+        assert!(fs::read_dir(&dir).unwrap().all(|entry| {
+            !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .ends_with(".tmp")
+        }));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn schema_0_databases_are_migrated_after_a_backup() {
+        let legacy = "Write tests,Cover the storage,5,home work\n#task-heap ideas,,7,\nJust a name\nTask,desc\n";
+        let (dir, path) = db_in("migrate", legacy);
+        let notices = Rc::new(RefCell::new(Vec::new()));
+        let mut storage = CsvStorage::new(&path).with_notify({
+            let notices = notices.clone();
+            Rc::new(move |notice: &Notice| notices.borrow_mut().push(notice.clone()))
+        });
+        let heap = storage.load().unwrap();
+        drop(storage);
+
+        assert_eq!(heap.len(), 4);
+        let task = &heap[&Task::hash_fn("Write tests")];
+        assert_eq!(task.get_weight(), 5);
+        assert_eq!(task.get_tags().len(), 2);
+        assert_eq!(heap[&Task::hash_fn("#task-heap ideas")].get_weight(), 7);
+        for name in ["Just a name", "Task"] {
+            assert_eq!(heap[&Task::hash_fn(name)].get_weight(), DEFAULT_WEIGHT);
+        }
+        let backup = path.with_extension("csv.v0.bak");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), legacy);
+        assert_eq!(*notices.borrow(), [Notice::Migrated { from: 0, backup }]);
+        let (migrated, version) = parse_heap(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        assert_eq!(migrated.len(), 4);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrations_back_up_into_the_backups_directory() {
+        let legacy = "Write tests,,5,\n";
+        let (dir, path) = db_in("backups", legacy);
+        let backups = Backups::new(dir.join("backups"), 5);
+        CsvStorage::new(&path)
+            .with_backups(Backups::new(dir.join("backups"), 5))
+            .load()
+            .unwrap();
+        let snapshots = backups.list().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].reason, "migration");
+        assert_eq!(fs::read_to_string(&snapshots[0].path).unwrap(), legacy);
+        assert!(!path.with_extension("csv.v0.bak").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn newer_schemas_are_refused_and_left_alone() {
+        let newer = format!("{MAGIC},{}\nWrite tests,,5,\n", SCHEMA_VERSION + 1);
+        let (dir, path) = db_in("newer", &newer);
+        assert!(matches!(
+            CsvStorage::new(&path).load(),
+            Err(HeapError::UnsupportedSchema(version)) if version == SCHEMA_VERSION + 1
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+use super::{FileLock, Notify, SCHEMA_VERSION, Storage, ignore_notices, write_atomically};
+use crate::{HeapError, Task, Tasks};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Write, path::PathBuf, time::Duration};
+
+#[derive(Serialize, Deserialize)]
+struct JsonHeap {
+    schema: u32,
+    tasks: Vec<Task>,
+}
+
+pub struct JsonStorage {
+    path: PathBuf,
+    lock_timeout: Duration,
+    lock: Option<FileLock>,
+    notify: Notify,
+}
+impl JsonStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock_timeout: Duration::ZERO,
+            lock: None,
+            notify: ignore_notices(),
+        }
+    }
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+    pub fn with_notify(mut self, notify: Notify) -> Self {
+        self.notify = notify;
+        self
+    }
+}
+impl Storage for JsonStorage {
+    fn lock(&mut self) -> Result<(), HeapError> {
+        if self.lock.is_none() {
+            self.lock = Some(FileLock::acquire(
+                &self.path,
+                self.lock_timeout,
+                &self.notify,
+            )?);
+        }
+        Ok(())
+    }
+    fn load(&mut self) -> Result<Tasks, HeapError> {
+        self.lock()?;
+        self.peek()
+    }
+    fn peek(&mut self) -> Result<Tasks, HeapError> {
+        if !self.path.exists() {
+            return Err(HeapError::FileDoesNotExist);
+        }
+        let data = fs::read_to_string(&self.path)?;
+        // Fields added by later schemas carry serde defaults, so older files
+        // deserialize as they are and only newer ones are refused.
+        let json_heap: JsonHeap =
+            serde_json::from_str(&data).map_err(|e| HeapError::CorruptData(e.to_string()))?;
+        if json_heap.schema > SCHEMA_VERSION {
+            return Err(HeapError::UnsupportedSchema(json_heap.schema));
+        }
+        Ok(json_heap
+            .tasks
+            .into_iter()
+            .map(|task| (task.get_hash(), task))
+            .collect())
+    }
+    fn save(&mut self, heap: &Tasks) -> Result<(), HeapError> {
+        let json_heap = JsonHeap {
+            schema: SCHEMA_VERSION,
+            tasks: heap.values().cloned().collect(),
+        };
+        write_atomically(&self.path, |writer| {
+            serde_json::to_writer_pretty(&mut *writer, &json_heap)?;
+            writeln!(writer)
+        })?;
+        Ok(())
+    }
+}
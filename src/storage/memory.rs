@@ -0,0 +1,24 @@
+use super::Storage;
+use crate::{HeapError, Tasks};
+
+// Keeps the heap in process memory only. Nothing touches the disk, which makes it
+// the backend of choice for tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    heap: Option<Tasks>,
+}
+#[cfg(test)]
+impl MemoryStorage {
+    pub fn heap(&self) -> Option<&Tasks> {
+        self.heap.as_ref()
+    }
+}
+impl Storage for MemoryStorage {
+    fn load(&mut self) -> Result<Tasks, HeapError> {
+        self.heap.clone().ok_or(HeapError::FileDoesNotExist)
+    }
+    fn save(&mut self, heap: &Tasks) -> Result<(), HeapError> {
+        self.heap = Some(heap.clone());
+        Ok(())
+    }
+}
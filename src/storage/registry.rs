@@ -0,0 +1,662 @@
+#[cfg(feature = "sqlite")]
+use super::SqliteStorage;
+use super::{
+    Backend, CsvStorage, FileLock, JsonStorage, MemoryStorage, Notice, Notify, Storage,
+    get_data_dir, ignore_notices, write_atomically,
+};
+use crate::{
+    HeapError, TaskHeap, Weighting, backup::Backups, completion::CompletionLog, config::Config,
+    csv, heap::check_ready, journal::Journal, task::DEFAULT_WEIGHT,
+};
+use rand::{Rng, distributions::WeightedIndex, prelude::Distribution};
+use std::{collections::HashMap, fs, io::Write, path::PathBuf, rc::Rc, time::Duration};
+
+// Heap used by databases written before named heaps existed.
+pub const DEFAULT_HEAP: &str = "default";
+const LEGACY_FILE_STEM: &str = "db";
+// `name,weight` records. It has no extension so it is never mistaken for a heap.
+const WEIGHTS_FILE: &str = "heap-weights";
+
+// Named heaps live side by side in the data directory, one `<name>.<extension>`
+// database each, all in the configured backend's format.
+pub struct HeapRegistry {
+    data_dir: PathBuf,
+    backend: Backend,
+    lock_timeout: Duration,
+    backup_retention: usize,
+    weighting: Weighting,
+    notify: Notify,
+}
+impl HeapRegistry {
+    pub fn new(config: &Config) -> Self {
+        Self::in_dir(get_data_dir(), config)
+    }
+    pub fn in_dir(data_dir: impl Into<PathBuf>, config: &Config) -> Self {
+        Self {
+            data_dir: data_dir.into(),
+            backend: config.backend,
+            lock_timeout: config.lock_timeout,
+            backup_retention: config.backup_retention,
+            weighting: Weighting {
+                aging: config.aging,
+                chicken: config.chicken_policy,
+            },
+            notify: ignore_notices(),
+        }
+    }
+    // Where the heaps' storage reports waiting for a lock, migrations and imports.
+    pub fn with_notify(mut self, notify: impl Fn(&Notice) + 'static) -> Self {
+        self.notify = Rc::new(notify);
+        self
+    }
+    pub fn open(&self, name: &str) -> Result<Box<dyn Storage>, HeapError> {
+        validate_name(name)?;
+        self.adopt_legacy_db()?;
+        Ok(match self.backend {
+            Backend::Csv => Box::new(
+                CsvStorage::new(self.path_for(name)?)
+                    .with_lock_timeout(self.lock_timeout)
+                    .with_backups(self.backups(name)?)
+                    .with_notify(self.notify.clone()),
+            ),
+            Backend::Json => Box::new(
+                JsonStorage::new(self.path_for(name)?)
+                    .with_lock_timeout(self.lock_timeout)
+                    .with_notify(self.notify.clone()),
+            ),
+            Backend::Memory => Box::new(MemoryStorage::default()),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => Box::new(
+                SqliteStorage::open(
+                    self.path_for(name)?,
+                    self.data_dir.join(format!("{name}.csv")),
+                    self.lock_timeout,
+                )?
+                .with_notify(self.notify.clone()),
+            ),
+        })
+    }
+    // The heap together with its journal, completion log and backups.
+    pub fn open_heap(&self, name: &str) -> Result<TaskHeap, HeapError> {
+        let mut storage = self.open(name)?;
+        // Another run may still be writing the journal and completion log, and reading
+        // them before it is done would reuse its entries and IDs.
+        storage.lock()?;
+        let journal = self.open_journal(name)?;
+        let completions = self.open_completions(name)?;
+        let heap = TaskHeap::open(storage, journal, self.backups(name)?)?;
+        Ok(heap
+            .with_weighting(self.weighting)
+            .with_completions(completions))
+    }
+    // The operation journal lives next to the heap's database.
+    pub fn open_journal(&self, name: &str) -> Result<Journal, HeapError> {
+        match self.backend {
+            Backend::Memory => Ok(Journal::in_memory()),
+            _ => Journal::open(self.journal_path_for(name)?),
+        }
+    }
+    // Like the journal, the completion log lives next to the heap's database.
+    pub fn open_completions(&self, name: &str) -> Result<CompletionLog, HeapError> {
+        match self.backend {
+            Backend::Memory => Ok(CompletionLog::in_memory()),
+            _ => CompletionLog::open(self.completions_path_for(name)?),
+        }
+    }
+    pub fn backups(&self, name: &str) -> Result<Backups, HeapError> {
+        validate_name(name)?;
+        Ok(match self.backend {
+            Backend::Memory => Backups::disabled(),
+            _ => Backups::new(self.backups_dir_for(name), self.backup_retention),
+        })
+    }
+    pub fn exists(&self, name: &str) -> Result<bool, HeapError> {
+        Ok(self.path_for(name)?.exists())
+    }
+    pub fn list(&self) -> Result<Vec<String>, HeapError> {
+        self.adopt_legacy_db()?;
+        let extension = self.extension()?;
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.data_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == extension)
+                && let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+                && validate_name(name).is_ok()
+            {
+                names.push(name.to_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+    pub fn create(&self, name: &str) -> Result<(), HeapError> {
+        validate_name(name)?;
+        if self.exists(name)? {
+            return Err(HeapError::HeapAlreadyExists(name.to_owned()));
+        }
+        let mut storage = self.open(name)?;
+        match storage.load() {
+            Ok(heap) => storage.save(&heap),
+            Err(HeapError::FileDoesNotExist) => storage.save(&Default::default()),
+            Err(e) => Err(e),
+        }
+    }
+    pub fn rename(&self, old: &str, new: &str) -> Result<(), HeapError> {
+        validate_name(new)?;
+        let old_path = self.existing_path_for(old)?;
+        let new_path = self.path_for(new)?;
+        if new_path.exists() {
+            return Err(HeapError::HeapAlreadyExists(new.to_owned()));
+        }
+        // Hold the heap's lock so no other process is halfway through using it. The lock
+        // file itself stays: unlinking it while held would let a waiting run lock the old
+        // file while the next one creates and locks a new one.
+        let _lock = FileLock::acquire(&old_path, self.lock_timeout, &self.notify)?;
+        fs::rename(&old_path, &new_path)?;
+        let old_journal = self.journal_path_for(old)?;
+        if old_journal.exists() {
+            fs::rename(&old_journal, self.journal_path_for(new)?)?;
+        }
+        let old_completions = self.completions_path_for(old)?;
+        if old_completions.exists() {
+            fs::rename(&old_completions, self.completions_path_for(new)?)?;
+        }
+        // Backups stay behind for a deleted heap, so only move them if nothing is in the way.
+        let (old_backups, new_backups) = (self.backups_dir_for(old), self.backups_dir_for(new));
+        if old_backups.exists() && !new_backups.exists() {
+            fs::rename(old_backups, new_backups)?;
+        }
+        self.update_weights(|weights| {
+            if let Some(weight) = weights.remove(old) {
+                weights.insert(new.to_owned(), weight);
+            }
+        })
+    }
+    pub fn delete(&self, name: &str) -> Result<(), HeapError> {
+        let path = self.existing_path_for(name)?;
+        let _lock = FileLock::acquire(&path, self.lock_timeout, &self.notify)?;
+        fs::remove_file(&path)?;
+        let journal = self.journal_path_for(name)?;
+        if journal.exists() {
+            fs::remove_file(journal)?;
+        }
+        let completions = self.completions_path_for(name)?;
+        if completions.exists() {
+            fs::remove_file(completions)?;
+        }
+        self.update_weights(|weights| {
+            weights.remove(name);
+        })
+    }
+    // Picks a heap by heap weight among those holding tasks that match `tags` and are
+    // ready to pop, so life areas get popped evenly no matter how many tasks each of
+    // them holds. Heaps are only peeked at: one that another run is using neither
+    // blocks the pick nor gets migrated by it.
+    pub fn pick<R: Rng + ?Sized>(&self, tags: &[String], rng: &mut R) -> Result<String, HeapError> {
+        let mut names = Vec::new();
+        let mut weights = Vec::new();
+        let mut any_waiting = false;
+        for name in self.list()? {
+            let weight = self.weight(&name)?;
+            if weight == 0 {
+                continue;
+            }
+            let tasks = match self.open(&name)?.peek() {
+                Ok(tasks) => tasks,
+                Err(HeapError::FileDoesNotExist) => continue,
+                Err(err) => return Err(err),
+            };
+            match check_ready(&tasks, self.weighting, tags) {
+                Ok(()) => {
+                    names.push(name);
+                    weights.push(u64::from(weight));
+                }
+                Err(HeapError::NoTasksReady) => any_waiting = true,
+                Err(HeapError::NoTaggedElements(_) | HeapError::NoTasksOnHeap) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if names.is_empty() {
+            if any_waiting {
+                return Err(HeapError::NoTasksReady);
+            } else if !tags.is_empty() {
+                return Err(HeapError::NoTaggedElements(tags.join(",")));
+            } else {
+                return Err(HeapError::NoTasksOnHeap);
+            }
+        }
+        // Summed as u64, so no number of heaps at u32::MAX can overflow it.
+        let distribution = WeightedIndex::new(&weights).map_err(|_| HeapError::NoTasksReady)?;
+        Ok(names.swap_remove(distribution.sample(rng)))
+    }
+    // Heaps without an explicit weight weigh the same as a default task.
+    pub fn weight(&self, name: &str) -> Result<u32, HeapError> {
+        Ok(self
+            .read_weights()?
+            .get(name)
+            .copied()
+            .unwrap_or(DEFAULT_WEIGHT))
+    }
+    pub fn set_weight(&self, name: &str, weight: u32) -> Result<(), HeapError> {
+        self.existing_path_for(name)?;
+        self.update_weights(|weights| {
+            weights.insert(name.to_owned(), weight);
+        })
+    }
+    fn read_weights(&self) -> Result<HashMap<String, u32>, HeapError> {
+        let path = self.data_dir.join(WEIGHTS_FILE);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        let data = fs::read_to_string(&path)?;
+        csv::split_records(&data)
+            .into_iter()
+            .map(|record| match csv::decode_record(record)?.as_slice() {
+                [name, weight] => weight
+                    .parse()
+                    .map(|weight| (name.to_owned(), weight))
+                    .map_err(|_| HeapError::CorruptData(record.to_owned())),
+                _ => Err(HeapError::CorruptData(record.to_owned())),
+            })
+            .collect()
+    }
+    fn update_weights<F>(&self, update: F) -> Result<(), HeapError>
+    where
+        F: FnOnce(&mut HashMap<String, u32>),
+    {
+        let path = self.data_dir.join(WEIGHTS_FILE);
+        let _lock = FileLock::acquire(&path, self.lock_timeout, &self.notify)?;
+        let mut weights = self.read_weights()?;
+        update(&mut weights);
+        let mut entries: Vec<_> = weights.into_iter().collect();
+        entries.sort();
+        write_atomically(&path, |writer| {
+            for (name, weight) in entries {
+                writeln!(writer, "{}", csv::encode_record([name, weight.to_string()]))?;
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+    fn extension(&self) -> Result<&'static str, HeapError> {
+        match self.backend {
+            Backend::Csv => Ok("csv"),
+            Backend::Json => Ok("json"),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => Ok("sqlite3"),
+            Backend::Memory => Err(HeapError::InvalidConfig(
+                "the memory backend does not keep named heaps".to_owned(),
+            )),
+        }
+    }
+    fn path_for(&self, name: &str) -> Result<PathBuf, HeapError> {
+        validate_name(name)?;
+        Ok(self.data_dir.join(format!("{name}.{}", self.extension()?)))
+    }
+    fn journal_path_for(&self, name: &str) -> Result<PathBuf, HeapError> {
+        validate_name(name)?;
+        Ok(self.data_dir.join(format!("{name}.journal")))
+    }
+    fn completions_path_for(&self, name: &str) -> Result<PathBuf, HeapError> {
+        validate_name(name)?;
+        Ok(self.data_dir.join(format!("{name}.done")))
+    }
+    fn backups_dir_for(&self, name: &str) -> PathBuf {
+        self.data_dir.join("backups").join(name)
+    }
+    fn existing_path_for(&self, name: &str) -> Result<PathBuf, HeapError> {
+        let path = self.path_for(name)?;
+        if !path.exists() {
+            return Err(HeapError::HeapNotFound(name.to_owned()));
+        }
+        Ok(path)
+    }
+    // Databases from before named heaps were called `db.<extension>`; they become
+    // the default heap the first time the registry is used.
+    fn adopt_legacy_db(&self) -> Result<(), HeapError> {
+        let Ok(extension) = self.extension() else {
+            return Ok(());
+        };
+        let default_path = self.data_dir.join(format!("{DEFAULT_HEAP}.{extension}"));
+        // A new SQLite default heap imports `default.csv`, so a legacy CSV database is
+        // adopted as that too.
+        let extensions = match self.backend {
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => vec![extension, "csv"],
+            _ => vec![extension],
+        };
+        for extension in extensions {
+            let legacy_path = self
+                .data_dir
+                .join(format!("{LEGACY_FILE_STEM}.{extension}"));
+            let adopted_path = self.data_dir.join(format!("{DEFAULT_HEAP}.{extension}"));
+            if legacy_path.exists() && !adopted_path.exists() && !default_path.exists() {
+                let _lock = FileLock::acquire(&legacy_path, self.lock_timeout, &self.notify)?;
+                fs::rename(&legacy_path, &adopted_path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Heap names become file names, so they are kept to a portable character set.
+pub fn validate_name(name: &str) -> Result<(), HeapError> {
+    let is_valid = !name.is_empty()
+        && name != LEGACY_FILE_STEM
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_valid {
+        Ok(())
+    } else {
+        Err(HeapError::InvalidHeapName(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Task, journal::Change};
+    use rand::{SeedableRng, rngs::StdRng};
+    use std::{collections::HashSet, thread};
+
+    fn registry_in(label: &str) -> (PathBuf, HeapRegistry) {
+        let dir =
+            std::env::temp_dir().join(format!("task-heap-registry-{label}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let registry = HeapRegistry::in_dir(&dir, &Config::default());
+        (dir, registry)
+    }
+
+    fn push(registry: &HeapRegistry, heap: &str, name: &str) {
+        let mut storage = registry.open(heap).unwrap();
+        let mut tasks = storage.load().unwrap();
+        let task = Task::from_arg(name);
+        tasks.insert(task.get_hash(), task);
+        storage.save(&tasks).unwrap();
+    }
+
+    fn holds(registry: &HeapRegistry, heap: &str, name: &str) -> bool {
+        let mut storage = registry.open(heap).unwrap();
+        storage.load().unwrap().contains_key(&Task::hash_fn(name))
+    }
+
+    #[test]
+    fn heaps_are_created_listed_and_deleted() {
+        let (dir, registry) = registry_in("lifecycle");
+        assert!(registry.list().unwrap().is_empty());
+        registry.create("work").unwrap();
+        registry.create("home").unwrap();
+        assert!(matches!(
+            registry.create("home"),
+            Err(HeapError::HeapAlreadyExists(_))
+        ));
+        assert_eq!(registry.list().unwrap(), ["home", "work"]);
+
+        push(&registry, "home", "dishes");
+        let mut journal = registry.open_journal("home").unwrap();
+        journal.record(
+            "push \"dishes\"",
+            vec![Change::new(None, Some(Task::from_arg("dishes")))],
+        );
+        journal.save().unwrap();
+        let mut completions = registry.open_completions("home").unwrap();
+        completions.record(Task::from_arg("laundry"), 0);
+        completions.save().unwrap();
+        registry.delete("home").unwrap();
+        assert_eq!(registry.list().unwrap(), ["work"]);
+        assert!(!dir.join("home.journal").exists());
+        assert!(!dir.join("home.done").exists());
+        assert!(matches!(
+            registry.delete("home"),
+            Err(HeapError::HeapNotFound(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renaming_moves_the_heap_and_its_files() {
+        let (dir, registry) = registry_in("rename");
+        registry.create("home").unwrap();
+        push(&registry, "home", "dishes");
+        let mut journal = registry.open_journal("home").unwrap();
+        journal.record(
+            "push \"dishes\"",
+            vec![Change::new(None, Some(Task::from_arg("dishes")))],
+        );
+        journal.save().unwrap();
+        let mut completions = registry.open_completions("home").unwrap();
+        completions.record(Task::from_arg("laundry"), 0);
+        completions.save().unwrap();
+        let heap = registry.open("home").unwrap().load().unwrap();
+        registry
+            .backups("home")
+            .unwrap()
+            .snapshot(&heap, "reset")
+            .unwrap();
+
+        registry.rename("home", "chores").unwrap();
+        assert_eq!(registry.list().unwrap(), ["chores"]);
+        for file in ["home.csv", "home.journal", "home.done", "backups/home"] {
+            assert!(!dir.join(file).exists(), "{file} was left behind");
+        }
+        assert!(holds(&registry, "chores", "dishes"));
+        assert_eq!(registry.backups("chores").unwrap().list().unwrap().len(), 1);
+        assert_eq!(
+            registry.open_journal("chores").unwrap().history(10).len(),
+            1
+        );
+        assert_eq!(registry.open_completions("chores").unwrap().all().len(), 1);
+        assert!(matches!(
+            registry.rename("home", "errands"),
+            Err(HeapError::HeapNotFound(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn renaming_onto_an_existing_heap_changes_nothing() {
+        let (dir, registry) = registry_in("collision");
+        for name in ["home", "work"] {
+            registry.create(name).unwrap();
+            push(&registry, name, name);
+        }
+        assert!(matches!(
+            registry.rename("home", "work"),
+            Err(HeapError::HeapAlreadyExists(_))
+        ));
+        for name in ["home", "work"] {
+            assert!(holds(&registry, name, name));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn heap_names_must_be_portable_file_names() {
+        for name in ["home", "side-projects", "2024_taxes"] {
+            assert!(validate_name(name).is_ok(), "{name}");
+        }
+        for name in ["", "db", "../home", "side projects", "home.csv", "tâches"] {
+            assert!(
+                matches!(validate_name(name), Err(HeapError::InvalidHeapName(_))),
+                "{name}"
+            );
+        }
+        let (dir, registry) = registry_in("names");
+        assert!(matches!(
+            registry.create("../escape"),
+            Err(HeapError::InvalidHeapName(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_legacy_database_becomes_the_default_heap() {
+        let (dir, registry) = registry_in("legacy");
+        fs::write(dir.join("db.csv"), "Write tests,,5,\n").unwrap();
+        assert_eq!(registry.list().unwrap(), [DEFAULT_HEAP]);
+        assert!(!dir.join("db.csv").exists());
+        assert!(holds(&registry, DEFAULT_HEAP, "Write tests"));
+
+        // Once there is a default heap, a legacy database is left alone.
+        fs::write(dir.join("db.csv"), "Old task,,5,\n").unwrap();
+        assert_eq!(registry.list().unwrap(), [DEFAULT_HEAP]);
+        assert!(dir.join("db.csv").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn a_new_sqlite_default_heap_imports_a_legacy_csv_database() {
+        let (dir, _) = registry_in("legacy-sqlite");
+        let registry = HeapRegistry::in_dir(
+            &dir,
+            &Config {
+                backend: Backend::Sqlite,
+                ..Config::default()
+            },
+        );
+        fs::write(dir.join("db.csv"), "Write tests,,5,\n").unwrap();
+        assert!(holds(&registry, DEFAULT_HEAP, "Write tests"));
+        assert!(!dir.join("db.csv").exists());
+        assert_eq!(registry.list().unwrap(), [DEFAULT_HEAP]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn heap_weights_follow_their_heap() {
+        let (dir, registry) = registry_in("weights");
+        registry.create("home").unwrap();
+        assert_eq!(registry.weight("home").unwrap(), DEFAULT_WEIGHT);
+        registry.set_weight("home", 30).unwrap();
+        assert_eq!(registry.weight("home").unwrap(), 30);
+        assert!(matches!(
+            registry.set_weight("work", 30),
+            Err(HeapError::HeapNotFound(_))
+        ));
+
+        registry.rename("home", "chores").unwrap();
+        assert_eq!(registry.weight("chores").unwrap(), 30);
+        registry.delete("chores").unwrap();
+        registry.create("chores").unwrap();
+        assert_eq!(registry.weight("chores").unwrap(), DEFAULT_WEIGHT);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn seeded_picks_follow_heap_weights() {
+        let (dir, registry) = registry_in("pick");
+        for (name, weight) in [("home", 1), ("paused", 0), ("work", 3)] {
+            registry.create(name).unwrap();
+            registry.set_weight(name, weight).unwrap();
+            push(&registry, name, name);
+        }
+        let picks = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..400)
+                .map(|_| registry.pick(&[], &mut rng).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let first = picks(7);
+        assert_eq!(first, picks(7));
+        assert!(!first.iter().any(|name| name == "paused"));
+        let work = first.iter().filter(|name| *name == "work").count();
+        assert!((250..350).contains(&work), "work was picked {work} times");
+
+        registry.set_weight("home", 0).unwrap();
+        registry.set_weight("work", 0).unwrap();
+        assert!(matches!(
+            registry.pick(&[], &mut StdRng::seed_from_u64(7)),
+            Err(HeapError::NoTasksOnHeap)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_waiting_run_reads_the_journal_once_the_heap_is_released() {
+        let (dir, registry) = registry_in("interleave");
+        registry.create("home").unwrap();
+        let mut heap = registry.open_heap("home").unwrap();
+        let waiting = thread::spawn({
+            let dir = dir.clone();
+            move || {
+                let mut heap = HeapRegistry::in_dir(dir, &Config::default())
+                    .open_heap("home")
+                    .unwrap();
+                heap.push(Task::from_arg("b")).unwrap();
+                heap.save().unwrap();
+                heap.get("b").unwrap().get_id()
+            }
+        });
+        // Give the other run time to start waiting for the lock.
+        thread::sleep(Duration::from_millis(200));
+        heap.push(Task::from_arg("a")).unwrap();
+        heap.delete("a").unwrap();
+        heap.save().unwrap();
+        drop(heap);
+
+        // Only the journal still knows "a" took ID 1.
+        assert_eq!(waiting.join().unwrap(), 2);
+        let heap = registry.open_heap("home").unwrap();
+        assert_eq!(heap.history(10).len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn meta_pop_skips_heaps_with_nothing_ready() {
+        let (dir, registry) = registry_in("ready");
+        let later = chrono::Utc::now().timestamp() + 3600;
+        for name in ["home", "work"] {
+            registry.create(name).unwrap();
+            let mut heap = registry.open_heap(name).unwrap();
+            heap.push(Task::from_arg(name)).unwrap();
+            heap.save().unwrap();
+        }
+        let mut heap = registry.open_heap("home").unwrap();
+        heap.snooze("home", later).unwrap();
+        heap.save().unwrap();
+        drop(heap);
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            assert_eq!(registry.pick(&[], &mut rng).unwrap(), "work");
+        }
+
+        let mut heap = registry.open_heap("work").unwrap();
+        heap.snooze("work", later).unwrap();
+        heap.save().unwrap();
+        drop(heap);
+        assert!(matches!(
+            registry.pick(&[], &mut rng),
+            Err(HeapError::NoTasksReady)
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn meta_pop_peeks_without_locking_or_migrating() {
+        let (dir, registry) = registry_in("peek");
+        for name in ["home", "work"] {
+            registry.create(name).unwrap();
+            registry.set_weight(name, u32::MAX).unwrap();
+        }
+        push(&registry, "home", "dishes");
+        // A heap written before the schema was versioned.
+        fs::write(dir.join("work.csv"), "Write the report,,5,\n").unwrap();
+        let mut home = registry.open("home").unwrap();
+        home.lock().unwrap();
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let picked: HashSet<String> = (0..20)
+            .map(|_| registry.pick(&[], &mut rng).unwrap())
+            .collect();
+        assert_eq!(picked.len(), 2);
+        assert_eq!(
+            fs::read_to_string(dir.join("work.csv")).unwrap(),
+            "Write the report,,5,\n"
+        );
+        assert!(registry.backups("work").unwrap().list().unwrap().is_empty());
+        drop(home);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
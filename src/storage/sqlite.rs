@@ -0,0 +1,379 @@
+use super::{CsvStorage, Notice, Notify, SCHEMA_VERSION, Storage, ignore_notices};
+use crate::{Hash, HeapError, Task, Tasks, csv};
+use rusqlite::{Connection, ErrorCode, params, params_from_iter};
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+// MIGRATIONS[n] upgrades the database from schema version n to n + 1.
+const MIGRATIONS: [&str; SCHEMA_VERSION as usize] = [
+    "
+    CREATE TABLE tasks (
+        hash BLOB PRIMARY KEY,
+        name TEXT NOT NULL,
+        description TEXT NOT NULL,
+        weight INTEGER NOT NULL
+    );
+    CREATE TABLE tags (
+        task BLOB NOT NULL REFERENCES tasks(hash) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (task, tag)
+    );
+    CREATE INDEX tags_by_tag ON tags(tag);
+    ",
+    // Existing tasks start aging now.
+    "
+    ALTER TABLE tasks ADD COLUMN created INTEGER NOT NULL DEFAULT 0;
+    UPDATE tasks SET created = CAST(strftime('%s', 'now') AS INTEGER);
+    ",
+    "
+    ALTER TABLE tasks ADD COLUMN rejections INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE tasks ADD COLUMN cooldown_until INTEGER NOT NULL DEFAULT 0;
+    ",
+    "
+    ALTER TABLE tasks ADD COLUMN recurrence TEXT NOT NULL DEFAULT '';
+    ALTER TABLE tasks ADD COLUMN hidden_until INTEGER NOT NULL DEFAULT 0;
+    ",
+    // Prerequisite names are kept as one CSV record, like in the CSV backend.
+    "ALTER TABLE tasks ADD COLUMN prerequisites TEXT NOT NULL DEFAULT '';",
+    "ALTER TABLE tasks ADD COLUMN parent TEXT;",
+    // Opening the heap numbers the tasks without an ID.
+    "ALTER TABLE tasks ADD COLUMN id INTEGER NOT NULL DEFAULT 0;",
+];
+
+// Changes are written row by row as they happen, inside one transaction that spans
+// the whole run: `load` opens it and `save` commits it. An error in between drops the
+// connection and rolls everything back, like the file backends not saving.
+pub struct SqliteStorage {
+    connection: Connection,
+    path: PathBuf,
+    // CSV database imported the first time the SQLite file is created.
+    import_path: PathBuf,
+    notify: Notify,
+}
+impl SqliteStorage {
+    pub fn open(
+        path: impl Into<PathBuf>,
+        import_path: impl Into<PathBuf>,
+        lock_timeout: Duration,
+    ) -> Result<Self, HeapError> {
+        let path = path.into();
+        let connection = Connection::open(&path)?;
+        connection.busy_timeout(lock_timeout)?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+        Ok(Self {
+            connection,
+            path,
+            import_path: import_path.into(),
+            notify: ignore_notices(),
+        })
+    }
+    pub fn with_notify(mut self, notify: Notify) -> Self {
+        self.notify = notify;
+        self
+    }
+    fn migrate(&mut self, version: u32) -> Result<(), HeapError> {
+        for migration in &MIGRATIONS[version as usize..] {
+            self.connection.execute_batch(migration)?;
+        }
+        self.connection
+            .pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        if version == 0 && self.import_path.exists() {
+            let heap = CsvStorage::new(&self.import_path)
+                .with_notify(self.notify.clone())
+                .load()?;
+            for task in heap.values() {
+                self.insert(task)?;
+            }
+            (self.notify)(&Notice::Imported {
+                count: heap.len(),
+                from: self.import_path.clone(),
+            });
+        }
+        Ok(())
+    }
+    fn insert_tags(&self, task: &Task) -> Result<(), HeapError> {
+        let mut statement = self
+            .connection
+            .prepare_cached("INSERT INTO tags (task, tag) VALUES (?1, ?2)")?;
+        for tag in task.get_tags() {
+            statement.execute(params![task.get_hash(), tag])?;
+        }
+        Ok(())
+    }
+}
+impl Storage for SqliteStorage {
+    fn lock(&mut self) -> Result<(), HeapError> {
+        if !self.connection.is_autocommit() {
+            return Ok(());
+        }
+        // IMMEDIATE takes the write lock now, so a concurrent run waits for this one
+        // to commit instead of both reading the same state.
+        match self.connection.execute_batch("BEGIN IMMEDIATE") {
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::DatabaseBusy => {
+                Err(HeapError::HeapLocked(self.path.clone()))
+            }
+            result => Ok(result?),
+        }
+    }
+    fn load(&mut self) -> Result<Tasks, HeapError> {
+        self.lock()?;
+        let version: u32 = self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(HeapError::UnsupportedSchema(version));
+        }
+        if version < SCHEMA_VERSION {
+            self.migrate(version)?;
+        }
+        read_tasks(&self.connection)
+    }
+    // Plain reads outside a transaction do not wait for the write lock a run holds.
+    fn peek(&mut self) -> Result<Tasks, HeapError> {
+        let version: u32 = self
+            .connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        match version {
+            SCHEMA_VERSION => read_tasks(&self.connection),
+            version if version > SCHEMA_VERSION => Err(HeapError::UnsupportedSchema(version)),
+            // A new database would only hold what it imports.
+            0 if self.import_path.exists() => CsvStorage::new(&self.import_path).peek(),
+            0 => Ok(Tasks::new()),
+            // Older schemas are migrated in a copy kept in memory.
+            version => {
+                let copy = Connection::open_in_memory()?;
+                copy.execute(
+                    "ATTACH DATABASE ?1 AS stored",
+                    [self.path.to_string_lossy()],
+                )?;
+                for migration in &MIGRATIONS[..version as usize] {
+                    copy.execute_batch(migration)?;
+                }
+                copy.execute_batch(
+                    "INSERT INTO main.tasks SELECT * FROM stored.tasks;
+                     INSERT INTO main.tags SELECT * FROM stored.tags;
+                     DETACH DATABASE stored;",
+                )?;
+                for migration in &MIGRATIONS[version as usize..] {
+                    copy.execute_batch(migration)?;
+                }
+                read_tasks(&copy)
+            }
+        }
+    }
+    fn save(&mut self, _heap: &Tasks) -> Result<(), HeapError> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+    fn insert(&mut self, task: &Task) -> Result<(), HeapError> {
+        self.connection.execute(
+            "INSERT INTO tasks (hash, name, description, weight, created, rejections, cooldown_until,
+                recurrence, hidden_until, prerequisites, parent, id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                task.get_hash(),
+                task.get_name(),
+                task.get_description(),
+                task.get_weight(),
+                task.get_created(),
+                task.get_rejections(),
+                task.get_cooldown_until(),
+                recurrence_of(task),
+                task.get_hidden_until(),
+                csv::encode_record(task.get_prerequisites()),
+                task.get_parent(),
+                task.get_id()
+            ],
+        )?;
+        self.insert_tags(task)
+    }
+    fn update(&mut self, old_hash: &Hash, task: &Task) -> Result<(), HeapError> {
+        self.connection
+            .execute("DELETE FROM tags WHERE task = ?1", params![old_hash])?;
+        self.connection.execute(
+            "UPDATE tasks SET hash = ?2, name = ?3, description = ?4, weight = ?5, created = ?6,
+                rejections = ?7, cooldown_until = ?8, recurrence = ?9, hidden_until = ?10,
+                prerequisites = ?11, parent = ?12, id = ?13
+             WHERE hash = ?1",
+            params![
+                old_hash,
+                task.get_hash(),
+                task.get_name(),
+                task.get_description(),
+                task.get_weight(),
+                task.get_created(),
+                task.get_rejections(),
+                task.get_cooldown_until(),
+                recurrence_of(task),
+                task.get_hidden_until(),
+                csv::encode_record(task.get_prerequisites()),
+                task.get_parent(),
+                task.get_id()
+            ],
+        )?;
+        self.insert_tags(task)
+    }
+    fn remove(&mut self, hash: &Hash) -> Result<(), HeapError> {
+        self.connection
+            .execute("DELETE FROM tasks WHERE hash = ?1", params![hash])?;
+        Ok(())
+    }
+    fn clear(&mut self) -> Result<(), HeapError> {
+        self.connection.execute("DELETE FROM tasks", [])?;
+        Ok(())
+    }
+    fn find_by_tags(&mut self, heap: &Tasks, tags: &[String]) -> Result<Vec<Hash>, HeapError> {
+        let tags: Vec<&String> = tags.iter().collect::<HashSet<_>>().into_iter().collect();
+        if tags.is_empty() {
+            return Ok(heap.keys().copied().collect());
+        }
+        // Tasks matching every tag appear once per tag in the indexed lookup.
+        let placeholders = vec!["?"; tags.len()].join(", ");
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT task FROM tags WHERE tag IN ({placeholders}) GROUP BY task HAVING COUNT(*) = {}",
+            tags.len()
+        ))?;
+        let hashes = statement
+            .query_map(params_from_iter(tags), |row| row.get::<_, Vec<u8>>(0))?
+            .map(|hash| {
+                hash?.try_into().map_err(|_| {
+                    HeapError::CorruptData("tag row with an invalid task hash".to_owned())
+                })
+            })
+            .collect::<Result<Vec<Hash>, HeapError>>()?;
+        Ok(hashes)
+    }
+}
+
+// Stored as text, empty for tasks that do not recur.
+fn read_tasks(connection: &Connection) -> Result<Tasks, HeapError> {
+    let mut heap = Tasks::new();
+    let mut statement = connection.prepare(
+        "SELECT name, description, weight, created, rejections, cooldown_until, recurrence,
+            hidden_until, prerequisites, parent, id
+         FROM tasks",
+    )?;
+    let rows = statement.query_map([], |row| {
+        let mut task = Task::new(
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get(2)?,
+            HashSet::new(),
+        );
+        task.set_created(row.get(3)?)
+            .set_rejections(row.get(4)?)
+            .set_cooldown_until(row.get(5)?)
+            .set_hidden_until(row.get(7)?)
+            .set_parent(row.get(9)?)
+            .set_id(row.get(10)?);
+        Ok((task, row.get::<_, String>(6)?, row.get::<_, String>(8)?))
+    })?;
+    for row in rows {
+        let (mut task, recurrence, prerequisites) = row?;
+        if !recurrence.is_empty() {
+            task.set_recurrence(Some(recurrence.parse().map_err(|_| {
+                HeapError::CorruptData(format!("recurrence \"{recurrence}\""))
+            })?));
+        }
+        if !prerequisites.is_empty() {
+            for name in csv::decode_record(&prerequisites)? {
+                task.add_prerequisite(name);
+            }
+        }
+        heap.insert(task.get_hash(), task);
+    }
+    let mut statement = connection.prepare("SELECT task, tag FROM tags")?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        let hash: Hash = row
+            .get::<_, Vec<u8>>(0)?
+            .try_into()
+            .map_err(|_| HeapError::CorruptData("tag row with an invalid task hash".to_owned()))?;
+        let tag: String = row.get(1)?;
+        if let Some(task) = heap.get_mut(&hash) {
+            task.add_tags(vec![tag]);
+        }
+    }
+    Ok(heap)
+}
+
+fn recurrence_of(task: &Task) -> String {
+    task.get_recurrence()
+        .map(|recurrence| recurrence.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incremental_updates_survive_reload() {
+        let mut storage =
+            SqliteStorage::open(":memory:", "does-not-exist.csv", Duration::ZERO).unwrap();
+        let heap = storage.load().unwrap();
+        assert!(heap.is_empty());
+
+        let mut task = Task::from_arg("Write tests");
+        task.add_tags(vec!["code".to_owned(), "rust".to_owned()]);
+        storage.insert(&task).unwrap();
+        storage.insert(&Task::from_arg("Water plants")).unwrap();
+        let old_hash = task.get_hash();
+        task.set_name("Write more tests")
+            .remove_tags(vec!["rust".to_owned()]);
+        storage.update(&old_hash, &task).unwrap();
+        storage.remove(&Task::hash_fn("Water plants")).unwrap();
+        storage.save(&heap).unwrap();
+
+        let heap = storage.load().unwrap();
+        assert_eq!(heap.len(), 1);
+        assert_eq!(heap[&task.get_hash()].get_tags(), ["code"]);
+        let tagged = storage.find_by_tags(&heap, &["code".to_owned()]).unwrap();
+        assert_eq!(tagged, [task.get_hash()]);
+        let tagged = storage.find_by_tags(&heap, &["rust".to_owned()]).unwrap();
+        assert!(tagged.is_empty());
+    }
+
+    #[test]
+    fn peeking_reads_an_old_schema_without_migrating_or_locking() {
+        let dir =
+            std::env::temp_dir().join(format!("task-heap-sqlite-peek-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("default.sqlite3");
+        let connection = Connection::open(&path).unwrap();
+        for migration in &MIGRATIONS[..2] {
+            connection.execute_batch(migration).unwrap();
+        }
+        connection.pragma_update(None, "user_version", 2).unwrap();
+        let task = Task::from_arg("Write tests");
+        connection
+            .execute(
+                "INSERT INTO tasks (hash, name, description, weight, created) VALUES (?1, ?2, '', 5, 0)",
+                params![task.get_hash(), task.get_name()],
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO tags (task, tag) VALUES (?1, 'code')",
+                params![task.get_hash()],
+            )
+            .unwrap();
+        drop(connection);
+
+        let open = || SqliteStorage::open(&path, dir.join("default.csv"), Duration::ZERO).unwrap();
+        let mut holder = open();
+        holder.lock().unwrap();
+        let heap = open().peek().unwrap();
+        assert_eq!(heap[&task.get_hash()].get_weight(), 5);
+        assert_eq!(heap[&task.get_hash()].get_tags(), ["code"]);
+        drop(holder);
+        let version: u32 = Connection::open(&path)
+            .unwrap()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
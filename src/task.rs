@@ -1,19 +1,47 @@
 use core::fmt;
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
-use crate::HeapError;
+use crate::{HeapError, csv, recurrence::Recurrence};
 
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use sha2::Digest;
 
-const DEFAULT_WEIGHT: u32 = 100;
+pub const DEFAULT_WEIGHT: u32 = 100;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Task {
     name: String,
     description: String,
     weight: u32,
     tags: HashSet<String>,
+    // Unix time the task was pushed; tasks from before it was recorded count from
+    // when they were first loaded.
+    #[serde(default = "now")]
+    created: i64,
+    // Times the task was offered by a pop and turned down.
+    #[serde(default)]
+    rejections: u32,
+    // Unix time until which pops pass the task over; 0 when it is not cooling down.
+    #[serde(default)]
+    cooldown_until: i64,
+    // Done tasks with a recurrence come back instead of leaving the heap.
+    #[serde(default)]
+    recurrence: Option<Recurrence>,
+    // Unix time until which the task is not due, so pops leave it alone; 0 when it is.
+    #[serde(default)]
+    hidden_until: i64,
+    // Names of the tasks that have to be done first.
+    #[serde(default)]
+    prerequisites: BTreeSet<String>,
+    // Name of the task this one is a subtask of.
+    #[serde(default)]
+    parent: Option<String>,
+    // Short number the heap gives the task when it is pushed; 0 until then.
+    #[serde(default)]
+    id: u32,
 }
 impl Task {
     pub fn new(
@@ -27,6 +55,14 @@ impl Task {
             description: description.into(),
             weight,
             tags,
+            created: now(),
+            rejections: 0,
+            cooldown_until: 0,
+            recurrence: None,
+            hidden_until: 0,
+            prerequisites: BTreeSet::new(),
+            parent: None,
+            id: 0,
         }
     }
     pub fn from_arg(name: impl Into<String>) -> Self {
@@ -35,6 +71,14 @@ impl Task {
             description: "".to_owned(),
             weight: DEFAULT_WEIGHT,
             tags: HashSet::new(),
+            created: now(),
+            rejections: 0,
+            cooldown_until: 0,
+            recurrence: None,
+            hidden_until: 0,
+            prerequisites: BTreeSet::new(),
+            parent: None,
+            id: 0,
         }
     }
     pub fn get_name(&self) -> &str {
@@ -101,12 +145,16 @@ impl Task {
         self.description = desc.into();
         self
     }
-    pub fn set_weight(&mut self, weight_str: impl AsRef<str>) -> &mut Self {
-        self.weight = weight_str.as_ref().parse().unwrap_or_else(|err| {
-            println!("Could not parse: {err}\nSetting weight to default value:{DEFAULT_WEIGHT}");
-            DEFAULT_WEIGHT
-        });
-        self
+    pub fn set_weight(&mut self, weight_str: impl AsRef<str>) -> Result<&mut Self, HeapError> {
+        self.weight = Task::parse_weight(weight_str)?;
+        Ok(self)
+    }
+    pub fn parse_weight(weight_str: impl AsRef<str>) -> Result<u32, HeapError> {
+        let weight_str = weight_str.as_ref();
+        weight_str
+            .trim()
+            .parse()
+            .map_err(|_| HeapError::InvalidArgument((weight_str.to_owned(), "weight".to_owned())))
     }
     pub fn add_tags(&mut self, tags: Vec<String>) -> &mut Self {
         for tag in tags {
@@ -127,42 +175,127 @@ impl Task {
     pub fn get_weight(&self) -> u32 {
         self.weight
     }
-    pub fn has_tags(&self, tags: &Vec<String>) -> bool {
+    pub fn has_tags(&self, tags: &[String]) -> bool {
         tags.iter().all(|tag| self.tags.contains(tag))
     }
+    pub fn get_created(&self) -> i64 {
+        self.created
+    }
+    pub fn set_created(&mut self, created: i64) -> &mut Self {
+        self.created = created;
+        self
+    }
+    pub fn get_rejections(&self) -> u32 {
+        self.rejections
+    }
+    pub fn set_rejections(&mut self, rejections: u32) -> &mut Self {
+        self.rejections = rejections;
+        self
+    }
+    pub fn get_cooldown_until(&self) -> i64 {
+        self.cooldown_until
+    }
+    pub fn set_cooldown_until(&mut self, cooldown_until: i64) -> &mut Self {
+        self.cooldown_until = cooldown_until;
+        self
+    }
+    pub fn is_cooling_down(&self, now: i64) -> bool {
+        self.cooldown_until > now
+    }
+    pub fn get_recurrence(&self) -> Option<&Recurrence> {
+        self.recurrence.as_ref()
+    }
+    pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) -> &mut Self {
+        self.recurrence = recurrence;
+        self
+    }
+    pub fn get_hidden_until(&self) -> i64 {
+        self.hidden_until
+    }
+    pub fn set_hidden_until(&mut self, hidden_until: i64) -> &mut Self {
+        self.hidden_until = hidden_until;
+        self
+    }
+    pub fn is_hidden(&self, now: i64) -> bool {
+        self.hidden_until > now
+    }
+    // Sorted by name.
+    pub fn get_prerequisites(&self) -> Vec<&str> {
+        self.prerequisites.iter().map(String::as_str).collect()
+    }
+    pub fn add_prerequisite(&mut self, name: impl Into<String>) -> &mut Self {
+        self.prerequisites.insert(name.into());
+        self
+    }
+    pub fn remove_prerequisite(&mut self, name: &str) -> bool {
+        self.prerequisites.remove(name)
+    }
+    pub fn get_parent(&self) -> Option<&str> {
+        self.parent.as_deref()
+    }
+    pub fn set_parent(&mut self, parent: Option<String>) -> &mut Self {
+        self.parent = parent;
+        self
+    }
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+    pub fn set_id(&mut self, id: u32) -> &mut Self {
+        self.id = id;
+        self
+    }
     pub fn get_tags(&self) -> Vec<&str> {
         self.tags.iter().map(|s| s.as_ref()).collect()
     }
-}
-impl FromStr for Task {
-    type Err = HeapError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split(',');
+    // Fields in on-disk column order, see storage::SCHEMA_VERSION.
+    pub fn to_fields(&self) -> Vec<String> {
+        let tags: String = self
+            .tags
+            .iter()
+            .map(|s| s.to_owned())
+            .collect::<Vec<String>>()
+            .join(" ");
+        vec![
+            self.name.clone(),
+            self.description.clone(),
+            self.weight.to_string(),
+            tags,
+            self.created.to_string(),
+            self.rejections.to_string(),
+            self.cooldown_until.to_string(),
+            self.recurrence
+                .as_ref()
+                .map(Recurrence::to_string)
+                .unwrap_or_default(),
+            self.hidden_until.to_string(),
+            csv::encode_record(&self.prerequisites),
+            self.parent.clone().unwrap_or_default(),
+            self.id.to_string(),
+        ]
+    }
+    pub fn from_fields(fields: &[String]) -> Result<Self, HeapError> {
+        let corrupt = || HeapError::CorruptData(csv::encode_record(fields));
+        let mut parts = fields.iter().map(String::as_str);
 
         // 1. Name: Strict (Must exist and not be empty)
         let name = parts
             .next()
-            .map(|s| s.trim()) // Clean up whitespace
             .filter(|s| !s.is_empty())
-            .ok_or(HeapError::CorruptData(s.to_string()))?
+            .ok_or_else(corrupt)?
             .to_string();
 
         // 2. Description: Permissive (Defaults to empty)
-        let description = parts.next().map(|s| s.trim()).unwrap_or("").to_string();
+        let description = parts.next().unwrap_or("").to_string();
 
         // 3. Weight: Strict on Garbage, Permissive on Missing
         // If the field is there ("100") but bad ("100a"), we return Error.
         // If the field is missing entirely, we use Default.
         let weight = match parts.next() {
-            Some(val) => val
-                .trim()
-                .parse()
-                .map_err(|_| HeapError::CorruptData(s.to_string()))?,
+            Some(val) => val.parse().map_err(|_| corrupt())?,
             None => DEFAULT_WEIGHT,
         };
 
-        // 4. Tags: Collect remaining
+        // 4. Tags: space separated
         let tags = parts
             .next()
             .unwrap_or("")
@@ -170,26 +303,115 @@ impl FromStr for Task {
             .map(String::from)
             .collect();
 
-        Ok(Task::new(name, description, weight, tags))
+        let mut task = Task::new(name, description, weight, tags);
+        // 5. Creation time: like the weight
+        if let Some(created) = parts.next() {
+            task.set_created(created.parse().map_err(|_| corrupt())?);
+        }
+        // 6. Rejections and cooldown: like the weight, defaulting to none
+        if let Some(rejections) = parts.next() {
+            task.set_rejections(rejections.parse().map_err(|_| corrupt())?);
+        }
+        if let Some(cooldown_until) = parts.next() {
+            task.set_cooldown_until(cooldown_until.parse().map_err(|_| corrupt())?);
+        }
+        // 7. Recurrence, empty for none, and the time it is hidden until
+        if let Some(recurrence) = parts.next().filter(|s| !s.is_empty()) {
+            task.set_recurrence(Some(recurrence.parse().map_err(|_| corrupt())?));
+        }
+        if let Some(hidden_until) = parts.next() {
+            task.set_hidden_until(hidden_until.parse().map_err(|_| corrupt())?);
+        }
+        // 8. Prerequisites: a record of names of their own, empty for none
+        if let Some(prerequisites) = parts.next().filter(|s| !s.is_empty()) {
+            for name in csv::decode_record(prerequisites)? {
+                task.add_prerequisite(name);
+            }
+        }
+        // 9. Parent: empty for a top-level task
+        if let Some(parent) = parts.next().filter(|s| !s.is_empty()) {
+            task.set_parent(Some(parent.to_owned()));
+        }
+        // 10. ID: like the weight, 0 until one is given
+        if let Some(id) = parts.next() {
+            task.set_id(id.parse().map_err(|_| corrupt())?);
+        }
+        Ok(task)
+    }
+}
+fn now() -> i64 {
+    Utc::now().timestamp()
+}
+
+impl FromStr for Task {
+    type Err = HeapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Task::from_fields(&csv::decode_record(s)?)
     }
 }
 impl Display for Task {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        //let mut tags = Vec::new();
-        //for tag in &self.tags {
-        //    tags.push(tag.to_owned());
-        //}
-        //let tags = tags.join(" ");
-        let tags: String = self
-            .tags
-            .iter()
-            .map(|s| s.to_owned())
-            .collect::<Vec<String>>()
-            .join(" ");
-        write!(
-            f,
-            "{},{},{},{}",
-            self.name, self.description, self.weight, tags
-        )
+        write!(f, "{}", csv::encode_record(self.to_fields()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn task_round_trip(
+            name in "[a-zA-Z,\" \n]*[a-zA-Z,\"\n][a-zA-Z,\" \n]*",
+            description in "[a-zA-Z,\" \r\n]*",
+            weight in any::<u32>(),
+            tags in proptest::collection::hash_set("[a-z\"]{1,8}", 0..4),
+            created in any::<i64>(),
+            rejections in any::<u32>(),
+            cooldown_until in any::<i64>(),
+            recurrence in proptest::option::of("daily|weekly|every:[1-9][0-9]{0,2}|weekdays:mon,sun"),
+            hidden_until in any::<i64>(),
+            prerequisites in proptest::collection::btree_set("[a-zA-Z,\" ]*[a-zA-Z,\"][a-zA-Z,\" ]*", 0..3),
+            parent in proptest::option::of("[a-zA-Z,\" \n]*[a-zA-Z,\"\n][a-zA-Z,\" \n]*"),
+            id in any::<u32>(),
+        ) {
+            let mut task = Task::new(name, description, weight, tags);
+            task.set_created(created)
+                .set_rejections(rejections)
+                .set_cooldown_until(cooldown_until)
+                .set_recurrence(recurrence.map(|rule| rule.parse().unwrap()))
+                .set_hidden_until(hidden_until)
+                .set_parent(parent)
+                .set_id(id);
+            for name in prerequisites {
+                task.add_prerequisite(name);
+            }
+            let parsed: Task = task.to_string().parse().unwrap();
+            prop_assert_eq!(parsed.get_name(), task.get_name());
+            prop_assert_eq!(parsed.get_description(), task.get_description());
+            prop_assert_eq!(parsed.get_weight(), task.get_weight());
+            prop_assert_eq!(parsed.tags, task.tags);
+            prop_assert_eq!(parsed.created, task.created);
+            prop_assert_eq!(parsed.rejections, task.rejections);
+            prop_assert_eq!(parsed.cooldown_until, task.cooldown_until);
+            prop_assert_eq!(parsed.recurrence, task.recurrence);
+            prop_assert_eq!(parsed.hidden_until, task.hidden_until);
+            prop_assert_eq!(parsed.prerequisites, task.prerequisites);
+            prop_assert_eq!(parsed.parent, task.parent);
+            prop_assert_eq!(parsed.id, task.id);
+        }
+    }
+
+    #[test]
+    fn legacy_row_still_parses() {
+        let task: Task = "Clean the garage,sweep first,50,home chores"
+            .parse()
+            .unwrap();
+        assert_eq!(task.get_name(), "Clean the garage");
+        assert_eq!(task.get_description(), "sweep first");
+        assert_eq!(task.get_weight(), 50);
+        assert!(task.has_tags(&["home".to_owned(), "chores".to_owned()]));
     }
 }